@@ -0,0 +1,664 @@
+// Raw instruction builders for the benchmarks. The program is loaded from its compiled .so,
+// so instructions are encoded by hand instead of linking the program crate and its older solana version.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::{json, Value};
+use solana_sdk::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+
+pub const PROGRAM_ID: Pubkey = solana_sdk::pubkey!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+pub const ONE_FCTR: u64 = 1_000_000_000_000;
+
+pub fn discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash(format!("global:{name}").as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+pub fn staking_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"staking"], &PROGRAM_ID).0
+}
+
+pub fn fctr_vault_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"fctr-vault"], &PROGRAM_ID).0
+}
+
+pub fn staker_info_pda(staker: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"staker-info", staker.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn confidant_offer_pda(confidant: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"confidant-offer", confidant.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn entrust_record_pda(confidant: &Pubkey, principal: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"entrust", confidant.as_ref(), principal.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn history_pda(staker: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"history", staker.as_ref()], &PROGRAM_ID).0
+}
+
+// Borsh encoding of the arguments, only the primitive types the program takes
+#[derive(Default)]
+pub struct Args(Vec<u8>);
+
+impl Args {
+    pub fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn bool(self, value: bool) -> Self {
+        self.u8(value as u8)
+    }
+
+    pub fn pubkey(mut self, value: &Pubkey) -> Self {
+        self.0.extend_from_slice(value.as_ref());
+        self
+    }
+}
+
+// Reads the Borsh encoded fields of a fetched account in order, starting past its discriminator
+pub struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Fields(&data[8..])
+    }
+
+    pub fn skip(&mut self, len: usize) -> &mut Self {
+        self.0 = &self.0[len..];
+        self
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        let value = u64::from_le_bytes(self.0[..8].try_into().unwrap());
+        self.skip(8);
+        value
+    }
+
+    pub fn bool(&mut self) -> bool {
+        let value = self.0[0] != 0;
+        self.skip(1);
+        value
+    }
+}
+
+fn instruction(name: &str, args: Args, accounts: Vec<AccountMeta>) -> Instruction {
+    let mut data = discriminator(name).to_vec();
+    data.extend(args.0);
+    Instruction { program_id: PROGRAM_ID, accounts, data }
+}
+
+pub fn initialize(owner: &Pubkey, round_time: u64, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, proof_signer: &Pubkey) -> Instruction {
+    instruction(
+        "initialize",
+        Args::default().u64(round_time).pubkey(fctr_mint).pubkey(bcdev_mint).pubkey(proof_signer),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn set_entrust_bounds(owner: &Pubkey, min_fraction_bps: u64, max_fraction_bps: u64, min_deposit_ratio_bps: u64, max_deposit_ratio_bps: u64) -> Instruction {
+    instruction(
+        "set_entrust_bounds",
+        Args::default().u64(min_fraction_bps).u64(max_fraction_bps).u64(min_deposit_ratio_bps).u64(max_deposit_ratio_bps),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+// PenaltySource and PenaltyDestination variants, encoded by their index
+pub const PENALTY_SOURCE_STAKE: u8 = 0;
+pub const PENALTY_SOURCE_REWARD: u8 = 1;
+pub const PENALTY_DESTINATION_REWARD_POOL: u8 = 0;
+pub const PENALTY_DESTINATION_TREASURY: u8 = 1;
+
+pub fn set_early_exit(owner: &Pubkey, enabled: bool, penalty_bps: u64, source: u8, destination: u8) -> Instruction {
+    instruction(
+        "set_early_exit",
+        Args::default().bool(enabled).u64(penalty_bps).u8(source).u8(destination),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+pub fn init_vault(owner: &Pubkey, fctr_mint: &Pubkey) -> Instruction {
+    instruction(
+        "init_vault",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(*fctr_mint, false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+    )
+}
+
+pub fn withdraw_treasury(owner: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, owner_fctr_account: &Pubkey, owner_bcdev_account: &Pubkey) -> Instruction {
+    instruction(
+        "withdraw_treasury",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*owner_fctr_account, false),
+            AccountMeta::new(*owner_bcdev_account, false),
+            AccountMeta::new(*bcdev_mint, false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub fn migrate_staking(owner: &Pubkey) -> Instruction {
+    instruction(
+        "migrate_staking",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+// Every principal kept inline by a legacy account takes its entrust record and staker info along
+pub fn migrate_staker_info(payer: &Pubkey, staker: &Pubkey, legacy_principals: &[Pubkey]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(staking_pda(), false),
+        AccountMeta::new(staker_info_pda(staker), false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    for principal in legacy_principals {
+        accounts.push(AccountMeta::new(entrust_record_pda(staker, principal), false));
+        accounts.push(AccountMeta::new(staker_info_pda(principal), false));
+    }
+    instruction("migrate_staker_info", Args::default().pubkey(staker), accounts)
+}
+
+pub fn fund(owner: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        "fund",
+        Args::default().u64(amount),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn register(staker: &Pubkey, proof_signer: &Pubkey) -> Instruction {
+    instruction(
+        "register",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(*proof_signer, true),
+            AccountMeta::new(staker_info_pda(staker), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn buy_fctr(user: &Pubkey, fctr_mint: &Pubkey, user_fctr_account: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        "buy_fctr",
+        Args::default().u64(amount),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(staker_info_pda(user), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(*user_fctr_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn stake(staker: &Pubkey, fctr_mint: &Pubkey, staker_fctr_account: &Pubkey, lock_tier: u8) -> Instruction {
+    instruction(
+        "stake",
+        Args::default().u8(lock_tier),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(staker_info_pda(staker), false),
+            AccountMeta::new(*staker_fctr_account, false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(*staker, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub fn publish_offer(confidant: &Pubkey, max_principals: u64, commission_bps: u64) -> Instruction {
+    instruction(
+        "publish_offer",
+        Args::default().bool(true).u64(max_principals).u64(commission_bps).u64(0),
+        vec![
+            AccountMeta::new_readonly(staker_info_pda(confidant), false),
+            AccountMeta::new(confidant_offer_pda(confidant), false),
+            AccountMeta::new(*confidant, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn update_offer(confidant: &Pubkey, accepting: bool, max_principals: u64, commission_bps: u64) -> Instruction {
+    instruction(
+        "update_offer",
+        Args::default().bool(accepting).u64(max_principals).u64(commission_bps).u64(0),
+        vec![
+            AccountMeta::new(confidant_offer_pda(confidant), false),
+            AccountMeta::new_readonly(*confidant, true),
+        ],
+    )
+}
+
+pub fn entrust(principal: &Pubkey, confidant: &Pubkey, fctr_mint: &Pubkey, principal_fctr_account: &Pubkey, amount: u64, term_rounds: u64) -> Instruction {
+    instruction(
+        "entrust",
+        Args::default().pubkey(confidant).u64(amount).u64(term_rounds),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*principal, true),
+            AccountMeta::new(staker_info_pda(principal), false),
+            AccountMeta::new(staker_info_pda(confidant), false),
+            AccountMeta::new_readonly(confidant_offer_pda(confidant), false),
+            AccountMeta::new(entrust_record_pda(confidant, principal), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(*principal_fctr_account, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn stake_for(payer: &Pubkey, beneficiary: &Pubkey, fctr_mint: &Pubkey, payer_fctr_account: &Pubkey, amount: u64, lock_tier: u8) -> Instruction {
+    instruction(
+        "stake_for",
+        Args::default().pubkey(beneficiary).u64(amount).u8(lock_tier),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(staker_info_pda(payer), false),
+            AccountMeta::new(staker_info_pda(beneficiary), false),
+            AccountMeta::new(*payer_fctr_account, false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub fn sell_fctr(user: &Pubkey, fctr_mint: &Pubkey, service_fctr_account: &Pubkey, user_fctr_account: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        "sell_fctr",
+        Args::default().u64(amount),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(staker_info_pda(user), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(*service_fctr_account, false),
+            AccountMeta::new(*user_fctr_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn sell_bcdev(user: &Pubkey, bcdev_mint: &Pubkey, service_bcdev_account: &Pubkey, user_bcdev_account: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        "sell_bcdev",
+        Args::default().u64(amount),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_bcdev_account, false),
+            AccountMeta::new(staker_info_pda(user), false),
+            AccountMeta::new(*bcdev_mint, false),
+            AccountMeta::new(*service_bcdev_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn close_staker_info(staker: &Pubkey) -> Instruction {
+    instruction(
+        "close_staker_info",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*staker, true),
+            AccountMeta::new(staker_info_pda(staker), false),
+        ],
+    )
+}
+
+pub fn set_sfctr_mint(owner: &Pubkey, sfctr_mint: &Pubkey) -> Instruction {
+    instruction(
+        "set_sfctr_mint",
+        Args::default(),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new_readonly(*sfctr_mint, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+pub fn stake_liquid(staker: &Pubkey, fctr_mint: &Pubkey, sfctr_mint: &Pubkey, staker_fctr_account: &Pubkey, staker_sfctr_account: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        "stake_liquid",
+        Args::default().u64(amount),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(staker_info_pda(staker), false),
+            AccountMeta::new(*staker_fctr_account, false),
+            AccountMeta::new(*staker_sfctr_account, false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(*sfctr_mint, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(*staker, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub struct HolderAccounts {
+    pub sfctr_account: Pubkey,
+    pub fctr_account: Pubkey,
+    pub bcdev_account: Pubkey,
+}
+
+// A registered holder gets its staker info passed along so that the redeemed tokens are recorded there
+pub fn redeem_sfctr(holder: &Pubkey, registered: bool, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, sfctr_mint: &Pubkey, accounts: &HolderAccounts, shares: u64) -> Instruction {
+    let mut metas = vec![
+        AccountMeta::new(staking_pda(), false),
+        AccountMeta::new(accounts.sfctr_account, false),
+        AccountMeta::new(accounts.fctr_account, false),
+        AccountMeta::new(accounts.bcdev_account, false),
+        AccountMeta::new(*bcdev_mint, false),
+        AccountMeta::new(*fctr_mint, false),
+        AccountMeta::new(*sfctr_mint, false),
+        AccountMeta::new(fctr_vault_pda(), false),
+        AccountMeta::new_readonly(*holder, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    if registered {
+        metas.push(AccountMeta::new(staker_info_pda(holder), false));
+    }
+    instruction("redeem_sfctr", Args::default().u64(shares), metas)
+}
+
+pub fn init_history(staker: &Pubkey) -> Instruction {
+    instruction(
+        "init_history",
+        Args::default(),
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(staker_info_pda(staker), false),
+            AccountMeta::new(history_pda(staker), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+// Anyone may sync a staker's recorded balances with its token accounts
+pub fn sync_balances(staker: &Pubkey, staker_fctr_account: &Pubkey, staker_bcdev_account: &Pubkey) -> Instruction {
+    instruction(
+        "sync_balances",
+        Args::default().pubkey(staker),
+        vec![
+            AccountMeta::new_readonly(staking_pda(), false),
+            AccountMeta::new(staker_info_pda(staker), false),
+            AccountMeta::new_readonly(*staker_fctr_account, false),
+            AccountMeta::new_readonly(*staker_bcdev_account, false),
+        ],
+    )
+}
+
+pub fn quote_buy_fctr(amount: u64) -> Instruction {
+    instruction("quote_buy_fctr", Args::default().u64(amount), vec![])
+}
+
+pub fn quote_sell_fctr(amount: u64) -> Instruction {
+    instruction("quote_sell_fctr", Args::default().u64(amount), vec![])
+}
+
+pub fn quote_sell_bcdev(amount: u64) -> Instruction {
+    instruction("quote_sell_bcdev", Args::default().u64(amount), vec![])
+}
+
+pub fn pending_rewards(staker: &Pubkey) -> Instruction {
+    instruction(
+        "pending_rewards",
+        Args::default().pubkey(staker),
+        vec![
+            AccountMeta::new_readonly(staking_pda(), false),
+            AccountMeta::new_readonly(staker_info_pda(staker), false),
+        ],
+    )
+}
+
+pub struct PrincipalAccounts {
+    pub principal: Pubkey,
+    pub fctr_account: Pubkey,
+    pub bcdev_account: Pubkey,
+}
+
+impl PrincipalAccounts {
+    // Laid out the way unstake and settle_principals take them as remaining accounts
+    fn metas(&self, confidant: &Pubkey) -> [AccountMeta; 5] {
+        [
+            AccountMeta::new(self.fctr_account, false),
+            AccountMeta::new(self.bcdev_account, false),
+            AccountMeta::new(staker_info_pda(&self.principal), false),
+            AccountMeta::new(entrust_record_pda(confidant, &self.principal), false),
+            AccountMeta::new(self.principal, false),
+        ]
+    }
+}
+
+pub fn unstake(staker: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, staker_fctr_account: &Pubkey, staker_bcdev_account: &Pubkey, principals: &[PrincipalAccounts]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(staking_pda(), false),
+        AccountMeta::new(staker_info_pda(staker), false),
+        AccountMeta::new(*staker_fctr_account, false),
+        AccountMeta::new(*staker_bcdev_account, false),
+        AccountMeta::new(*bcdev_mint, false),
+        AccountMeta::new(*fctr_mint, false),
+        AccountMeta::new(fctr_vault_pda(), false),
+        AccountMeta::new_readonly(*staker, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for principal in principals {
+        accounts.extend(principal.metas(staker));
+    }
+    instruction("unstake", Args::default(), accounts)
+}
+
+pub fn settle_principals(confidant: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, principals: &[PrincipalAccounts]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(staking_pda(), false),
+        AccountMeta::new(staker_info_pda(confidant), false),
+        AccountMeta::new(*bcdev_mint, false),
+        AccountMeta::new(*fctr_mint, false),
+        AccountMeta::new(fctr_vault_pda(), false),
+        AccountMeta::new_readonly(*confidant, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for principal in principals {
+        accounts.extend(principal.metas(confidant));
+    }
+    instruction("settle_principals", Args::default(), accounts)
+}
+
+pub fn demand_back(principal: &Pubkey, confidant: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, principal_accounts: &PrincipalAccounts) -> Instruction {
+    instruction(
+        "demand_back",
+        Args::default().pubkey(confidant),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*principal, true),
+            AccountMeta::new(staker_info_pda(principal), false),
+            AccountMeta::new(staker_info_pda(confidant), false),
+            AccountMeta::new(entrust_record_pda(confidant, principal), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(principal_accounts.fctr_account, false),
+            AccountMeta::new(*bcdev_mint, false),
+            AccountMeta::new(principal_accounts.bcdev_account, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn release_principal(confidant: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, principal: &PrincipalAccounts) -> Instruction {
+    instruction(
+        "release_principal",
+        Args::default().pubkey(&principal.principal),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new(*confidant, true),
+            AccountMeta::new(principal.principal, false),
+            AccountMeta::new(staker_info_pda(&principal.principal), false),
+            AccountMeta::new(staker_info_pda(confidant), false),
+            AccountMeta::new(entrust_record_pda(confidant, &principal.principal), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(principal.fctr_account, false),
+            AccountMeta::new(*bcdev_mint, false),
+            AccountMeta::new(principal.bcdev_account, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn slash_confidant(authority: &Pubkey, confidant: &Pubkey, fctr_mint: &Pubkey, principal: &PrincipalAccounts, slash_bps: u64) -> Instruction {
+    instruction(
+        "slash_confidant",
+        Args::default().pubkey(confidant).pubkey(&principal.principal).u64(slash_bps),
+        vec![
+            AccountMeta::new(staking_pda(), false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(staker_info_pda(confidant), false),
+            AccountMeta::new(staker_info_pda(&principal.principal), false),
+            AccountMeta::new_readonly(entrust_record_pda(confidant, &principal.principal), false),
+            AccountMeta::new(*fctr_mint, false),
+            AccountMeta::new(principal.fctr_account, false),
+            AccountMeta::new(fctr_vault_pda(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+// Size the pre-versioning program allocated for the staking account
+pub const LEGACY_STAKING_LEN: usize = 8*7 + 32*4 + 1;
+pub const LEGACY_STAKER_INFO_LEN: usize = 32 + 8*8 + 5 + (4 + (32 + 8) * 4);
+
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash(format!("account:{name}").as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+// Account data laid out the way the pre-versioning program serialized it, for benchmarking the migrations
+pub fn legacy_staking(owner: &Pubkey, round_time: u64, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, proof_signer: &Pubkey) -> Vec<u8> {
+    let bump = Pubkey::find_program_address(&[b"staking"], &PROGRAM_ID).1;
+    let fields = Args::default()
+        .pubkey(owner).u64(round_time).u64(0).u64(0).u64(0).u64(0)
+        .bool(false).u64(0)
+        .pubkey(fctr_mint).pubkey(bcdev_mint).pubkey(proof_signer)
+        .u8(bump);
+    let mut data = account_discriminator("Staking").to_vec();
+    data.extend(fields.0);
+    data.resize(8 + LEGACY_STAKING_LEN, 0);
+    data
+}
+
+pub fn legacy_staker_info(staker: &Pubkey, stake_size: u64, stake_time: u64, principals: &[(Pubkey, u64)]) -> Vec<u8> {
+    let bump = Pubkey::find_program_address(&[b"staker-info", staker.as_ref()], &PROGRAM_ID).1;
+    let mut fields = Args::default()
+        .pubkey(staker).u64(stake_size).u64(stake_time).u64(0).u64(0).u64(0).u64(stake_time).u64(1).u64(0)
+        .bool(false).bool(stake_size > 0).bool(!principals.is_empty());
+    fields.0.extend_from_slice(&(principals.len() as u32).to_le_bytes());
+    for (principal, amount) in principals {
+        fields = fields.pubkey(principal).u64(*amount);
+    }
+    let mut data = account_discriminator("StakerInfo").to_vec();
+    data.extend(fields.u8(bump).0);
+    data.resize(8 + LEGACY_STAKER_INFO_LEN, 0);
+    data
+}
+
+// Checked-in compute unit figures, a run fails when an instruction grows past the threshold
+pub struct Report {
+    pub threshold_percent: u64,
+    pub compute_units: BTreeMap<String, u64>,
+}
+
+impl Report {
+    pub fn load(path: &Path) -> Self {
+        let report: Value = serde_json::from_str(&fs::read_to_string(path).expect("CU report is missing")).expect("CU report is not valid JSON");
+        let compute_units = report["compute_units"]
+            .as_object()
+            .map(|units| units.iter().map(|(name, units)| (name.clone(), units.as_u64().unwrap())).collect())
+            .unwrap_or_default();
+        Report { threshold_percent: report["threshold_percent"].as_u64().unwrap(), compute_units }
+    }
+
+    pub fn save(&self, path: &Path) {
+        let report = json!({ "threshold_percent": self.threshold_percent, "compute_units": self.compute_units });
+        fs::write(path, serde_json::to_string_pretty(&report).unwrap() + "\n").unwrap();
+    }
+
+    // Measured instructions the baseline has no figure for, a new instruction has to be recorded before it is gated
+    pub fn missing(&self, measured: &BTreeMap<String, u64>) -> Vec<String> {
+        measured.keys().filter(|name| !self.compute_units.contains_key(*name)).cloned().collect()
+    }
+
+    // Names of the instructions that got more expensive than the baseline allows
+    pub fn regressions(&self, measured: &BTreeMap<String, u64>) -> Vec<String> {
+        measured
+            .iter()
+            .filter_map(|(name, &units)| {
+                let baseline = *self.compute_units.get(name)?;
+                (units * 100 > baseline * (100 + self.threshold_percent)).then(|| format!("{name}: {baseline} -> {units} CU"))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,372 @@
+use std::{collections::BTreeMap, path::Path};
+
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    clock::Clock,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use solana_staking_bench::*;
+
+const ROUND_TIME: u64 = 1;
+// Past four principals the unstake accounts no longer fit into a legacy transaction
+const PRINCIPAL_COUNTS: [usize; 5] = [0, 1, 2, 3, 4];
+
+struct Bench {
+    context: ProgramTestContext,
+    proof_signer: Keypair,
+    fctr_mint: Pubkey,
+    bcdev_mint: Pubkey,
+    measured: BTreeMap<String, u64>,
+}
+
+struct User {
+    keypair: Keypair,
+    fctr_account: Pubkey,
+    bcdev_account: Pubkey,
+}
+
+impl Bench {
+    async fn new() -> Self {
+        if std::env::var("SBF_OUT_DIR").is_err() && std::env::var("BPF_OUT_DIR").is_err() {
+            std::env::set_var("SBF_OUT_DIR", "../target/deploy");
+        }
+        let mut program_test = ProgramTest::new("solana_staking", PROGRAM_ID, None);
+        program_test.prefer_bpf(true);
+
+        let mut bench = Bench {
+            context: program_test.start_with_context().await,
+            proof_signer: Keypair::new(),
+            fctr_mint: Pubkey::default(),
+            bcdev_mint: Pubkey::default(),
+            measured: BTreeMap::new(),
+        };
+        bench.fctr_mint = bench.create_mint(12).await;
+        bench.bcdev_mint = bench.create_mint(18).await;
+
+        let owner = bench.context.payer.pubkey();
+        let (fctr_mint, bcdev_mint, proof_signer) = (bench.fctr_mint, bench.bcdev_mint, bench.proof_signer.pubkey());
+        bench.measure("initialize", vec![initialize(&owner, ROUND_TIME, &fctr_mint, &bcdev_mint, &proof_signer)], &[]).await;
+        // Wide deposit ratio so that one confidant can take many principals
+        bench.run(vec![set_entrust_bounds(&owner, 2500, 5000, 0, 1_000_000)], &[]).await;
+        bench.run(vec![fund(&owner, 100 * LAMPORTS_PER_SOL)], &[]).await;
+        bench
+    }
+
+    async fn run(&mut self, instructions: Vec<Instruction>, signers: &[&Keypair]) -> u64 {
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend(signers);
+        let transaction = Transaction::new_signed_with_payer(&instructions, Some(&self.context.payer.pubkey()), &all_signers, blockhash);
+
+        let outcome = self.context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+        let metadata = outcome.metadata.unwrap();
+        if let Err(err) = outcome.result {
+            panic!("{err:?}\n{}", metadata.log_messages.join("\n"));
+        }
+        metadata.compute_units_consumed
+    }
+
+    async fn measure(&mut self, name: &str, instructions: Vec<Instruction>, signers: &[&Keypair]) {
+        let units = self.run(instructions, signers).await;
+        self.measured.insert(name.to_string(), units);
+    }
+
+    async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.context.payer.pubkey();
+        self.run(vec![
+            system_instruction::create_account(&payer, &mint.pubkey(), rent.minimum_balance(spl_token::state::Mint::LEN), spl_token::state::Mint::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &staking_pda(), None, decimals).unwrap(),
+        ], &[&mint]).await;
+        mint.pubkey()
+    }
+
+    async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.context.payer.pubkey();
+        self.run(vec![
+            system_instruction::create_account(&payer, &account.pubkey(), rent.minimum_balance(spl_token::state::Account::LEN), spl_token::state::Account::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), mint, owner).unwrap(),
+        ], &[&account]).await;
+        account.pubkey()
+    }
+
+    // A registered user holding `fctr_amount` FCTR bought from the program
+    async fn user(&mut self, fctr_amount: u64) -> User {
+        let keypair = Keypair::new();
+        let payer = self.context.payer.pubkey();
+        self.run(vec![system_instruction::transfer(&payer, &keypair.pubkey(), 10 * LAMPORTS_PER_SOL)], &[]).await;
+
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let fctr_account = self.create_token_account(&fctr_mint, &keypair.pubkey()).await;
+        let bcdev_account = self.create_token_account(&bcdev_mint, &keypair.pubkey()).await;
+
+        let proof_signer = self.proof_signer.insecure_clone();
+        self.measure("register", vec![register(&keypair.pubkey(), &proof_signer.pubkey())], &[&keypair, &proof_signer]).await;
+        self.measure("buy_fctr", vec![buy_fctr(&keypair.pubkey(), &fctr_mint, &fctr_account, fctr_amount)], &[&keypair]).await;
+        User { keypair, fctr_account, bcdev_account }
+    }
+
+    async fn advance_rounds(&mut self, rounds: u64) {
+        let mut clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        clock.unix_timestamp += (rounds * ROUND_TIME) as i64;
+        self.context.set_sysvar(&clock);
+    }
+
+    // A staked confidant holding `principals_count` entrustments, measured when it unstakes and releases them all
+    async fn unstake_with_principals(&mut self, principals_count: usize) {
+        let fctr_mint = self.fctr_mint;
+        let bcdev_mint = self.bcdev_mint;
+
+        let confidant = self.user(100 * ONE_FCTR).await;
+        let confidant_key = confidant.keypair.pubkey();
+        self.measure("stake", vec![stake(&confidant_key, &fctr_mint, &confidant.fctr_account, 0)], &[&confidant.keypair]).await;
+        self.run(vec![publish_offer(&confidant_key, principals_count as u64, 500)], &[&confidant.keypair]).await;
+
+        let mut principals = vec![];
+        for _ in 0..principals_count {
+            let principal = self.user(10 * ONE_FCTR).await;
+            let principal_key = principal.keypair.pubkey();
+            self.measure("entrust", vec![entrust(&principal_key, &confidant_key, &fctr_mint, &principal.fctr_account, 5 * ONE_FCTR / 2, 0)], &[&principal.keypair]).await;
+            principals.push(PrincipalAccounts { principal: principal_key, fctr_account: principal.fctr_account, bcdev_account: principal.bcdev_account });
+        }
+
+        self.advance_rounds(2).await;
+        let instruction = unstake(&confidant_key, &fctr_mint, &bcdev_mint, &confidant.fctr_account, &confidant.bcdev_account, &principals);
+        self.measure(&format!("unstake/{principals_count}_principals"), vec![instruction], &[&confidant.keypair]).await;
+    }
+
+    async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        self.context.banks_client.get_packed_account_data::<spl_token::state::Account>(*account).await.unwrap().amount
+    }
+
+    // A staker leaving the program: sells everything it got back and closes its staker info
+    async fn exit(&mut self) {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let staking = staking_pda();
+        let service_fctr_account = self.create_token_account(&fctr_mint, &staking).await;
+        let service_bcdev_account = self.create_token_account(&bcdev_mint, &staking).await;
+
+        let staker = self.user(100 * ONE_FCTR).await;
+        let staker_key = staker.keypair.pubkey();
+        self.run(vec![stake(&staker_key, &fctr_mint, &staker.fctr_account, 0)], &[&staker.keypair]).await;
+        self.advance_rounds(2).await;
+        self.run(vec![unstake(&staker_key, &fctr_mint, &bcdev_mint, &staker.fctr_account, &staker.bcdev_account, &[])], &[&staker.keypair]).await;
+        self.measure("sync_balances", vec![sync_balances(&staker_key, &staker.fctr_account, &staker.bcdev_account)], &[]).await;
+
+        let fctr_amount = self.token_balance(&staker.fctr_account).await;
+        let bcdev_amount = self.token_balance(&staker.bcdev_account).await;
+        self.measure("sell_fctr", vec![sell_fctr(&staker_key, &fctr_mint, &service_fctr_account, &staker.fctr_account, fctr_amount)], &[&staker.keypair]).await;
+        self.measure("sell_bcdev", vec![sell_bcdev(&staker_key, &bcdev_mint, &service_bcdev_account, &staker.bcdev_account, bcdev_amount)], &[&staker.keypair]).await;
+        self.measure("close_staker_info", vec![close_staker_info(&staker_key)], &[&staker.keypair]).await;
+    }
+
+    // Opening a position for someone else, then topping it up
+    async fn stake_for_beneficiary(&mut self) {
+        let fctr_mint = self.fctr_mint;
+        let payer = self.user(20 * ONE_FCTR).await;
+        let beneficiary = self.user(10 * ONE_FCTR).await;
+        let (payer_key, beneficiary_key) = (payer.keypair.pubkey(), beneficiary.keypair.pubkey());
+
+        self.measure("stake_for/new_position", vec![stake_for(&payer_key, &beneficiary_key, &fctr_mint, &payer.fctr_account, 10 * ONE_FCTR, 0)], &[&payer.keypair]).await;
+        self.measure("stake_for/top_up", vec![stake_for(&payer_key, &beneficiary_key, &fctr_mint, &payer.fctr_account, 10 * ONE_FCTR, 0)], &[&payer.keypair]).await;
+    }
+
+    async fn principal(&mut self, confidant: &Pubkey) -> (Keypair, PrincipalAccounts) {
+        let fctr_mint = self.fctr_mint;
+        let principal = self.user(10 * ONE_FCTR).await;
+        let principal_key = principal.keypair.pubkey();
+        self.run(vec![entrust(&principal_key, confidant, &fctr_mint, &principal.fctr_account, 5 * ONE_FCTR / 2, 0)], &[&principal.keypair]).await;
+        (principal.keypair, PrincipalAccounts { principal: principal_key, fctr_account: principal.fctr_account, bcdev_account: principal.bcdev_account })
+    }
+
+    // Every way an entrustment ends short of the confidant unstaking, plus the arbiter's slash
+    async fn entrustments(&mut self) {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let confidant = self.user(100 * ONE_FCTR).await;
+        let confidant_key = confidant.keypair.pubkey();
+        self.run(vec![stake(&confidant_key, &fctr_mint, &confidant.fctr_account, 0)], &[&confidant.keypair]).await;
+        self.measure("publish_offer", vec![publish_offer(&confidant_key, 4, 500)], &[&confidant.keypair]).await;
+        self.measure("update_offer", vec![update_offer(&confidant_key, true, 5, 500)], &[&confidant.keypair]).await;
+
+        let (demanding, demanding_accounts) = self.principal(&confidant_key).await;
+        let (_, released) = self.principal(&confidant_key).await;
+        let (_, first_settled) = self.principal(&confidant_key).await;
+        let (_, second_settled) = self.principal(&confidant_key).await;
+        let (_, compensated) = self.principal(&confidant_key).await;
+        self.advance_rounds(2).await;
+
+        let owner = self.context.payer.pubkey();
+        self.measure("slash_confidant", vec![slash_confidant(&owner, &confidant_key, &fctr_mint, &compensated, 1000)], &[]).await;
+        self.measure("demand_back", vec![demand_back(&demanding.pubkey(), &confidant_key, &fctr_mint, &bcdev_mint, &demanding_accounts)], &[&demanding]).await;
+        self.measure("release_principal", vec![release_principal(&confidant_key, &fctr_mint, &bcdev_mint, &released)], &[&confidant.keypair]).await;
+        self.measure("settle_principals/2_principals", vec![settle_principals(&confidant_key, &fctr_mint, &bcdev_mint, &[first_settled, second_settled])], &[&confidant.keypair]).await;
+    }
+
+    // Staking for sFCTR and redeeming it, both by the registered staker and by an unregistered holder it was sent to
+    async fn liquid_staking(&mut self) {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let sfctr_mint = self.create_mint(12).await;
+        let owner = self.context.payer.pubkey();
+        self.measure("set_sfctr_mint", vec![set_sfctr_mint(&owner, &sfctr_mint)], &[]).await;
+
+        let staker = self.user(100 * ONE_FCTR).await;
+        let staker_key = staker.keypair.pubkey();
+        let staker_accounts = HolderAccounts {
+            sfctr_account: self.create_token_account(&sfctr_mint, &staker_key).await,
+            fctr_account: staker.fctr_account,
+            bcdev_account: staker.bcdev_account,
+        };
+        self.measure("stake_liquid", vec![stake_liquid(&staker_key, &fctr_mint, &sfctr_mint, &staker.fctr_account, &staker_accounts.sfctr_account, 100 * ONE_FCTR)], &[&staker.keypair]).await;
+        self.advance_rounds(2).await;
+
+        let holder = Keypair::new();
+        let holder_accounts = HolderAccounts {
+            sfctr_account: self.create_token_account(&sfctr_mint, &holder.pubkey()).await,
+            fctr_account: self.create_token_account(&fctr_mint, &holder.pubkey()).await,
+            bcdev_account: self.create_token_account(&bcdev_mint, &holder.pubkey()).await,
+        };
+        let shares = self.token_balance(&staker_accounts.sfctr_account).await / 2;
+        self.run(vec![
+            spl_token::instruction::transfer(&spl_token::id(), &staker_accounts.sfctr_account, &holder_accounts.sfctr_account, &staker_key, &[], shares).unwrap()
+        ], &[&staker.keypair]).await;
+
+        self.measure("redeem_sfctr/registered", vec![redeem_sfctr(&staker_key, true, &fctr_mint, &bcdev_mint, &sfctr_mint, &staker_accounts, shares)], &[&staker.keypair]).await;
+        self.measure("redeem_sfctr/unregistered", vec![redeem_sfctr(&holder.pubkey(), false, &fctr_mint, &bcdev_mint, &sfctr_mint, &holder_accounts, shares)], &[&holder]).await;
+    }
+
+    async fn views(&mut self) {
+        let fctr_mint = self.fctr_mint;
+        self.measure("quote_buy_fctr", vec![quote_buy_fctr(10 * ONE_FCTR)], &[]).await;
+        self.measure("quote_sell_fctr", vec![quote_sell_fctr(10 * ONE_FCTR)], &[]).await;
+        self.measure("quote_sell_bcdev", vec![quote_sell_bcdev(ONE_FCTR)], &[]).await;
+
+        let staker = self.user(10 * ONE_FCTR).await;
+        let staker_key = staker.keypair.pubkey();
+        self.run(vec![stake(&staker_key, &fctr_mint, &staker.fctr_account, 0)], &[&staker.keypair]).await;
+        self.advance_rounds(1).await;
+        self.measure("pending_rewards", vec![pending_rewards(&staker_key)], &[]).await;
+        self.measure("init_history", vec![init_history(&staker_key)], &[&staker.keypair]).await;
+    }
+
+    // An early exit with the penalty routed to the treasury, which the owner then withdraws
+    async fn treasury(&mut self) {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let owner = self.context.payer.pubkey();
+        self.run(vec![set_early_exit(&owner, true, 1000, PENALTY_SOURCE_STAKE, PENALTY_DESTINATION_TREASURY)], &[]).await;
+
+        let staker = self.user(100 * ONE_FCTR).await;
+        let staker_key = staker.keypair.pubkey();
+        self.run(vec![stake(&staker_key, &fctr_mint, &staker.fctr_account, 1)], &[&staker.keypair]).await;
+        self.advance_rounds(1).await;
+        self.measure("unstake/early_exit", vec![unstake(&staker_key, &fctr_mint, &bcdev_mint, &staker.fctr_account, &staker.bcdev_account, &[])], &[&staker.keypair]).await;
+        self.run(vec![set_early_exit(&owner, false, 1000, PENALTY_SOURCE_STAKE, PENALTY_DESTINATION_REWARD_POOL)], &[]).await;
+
+        let owner_fctr_account = self.create_token_account(&fctr_mint, &owner).await;
+        let owner_bcdev_account = self.create_token_account(&bcdev_mint, &owner).await;
+        self.measure("withdraw_treasury", vec![withdraw_treasury(&owner, &fctr_mint, &bcdev_mint, &owner_fctr_account, &owner_bcdev_account)], &[]).await;
+    }
+
+    // Staking, entrusting and unstaking with the FCTR held in the vault instead of burnt, needs a pool nothing was staked in yet
+    async fn escrow(&mut self) {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let owner = self.context.payer.pubkey();
+        self.measure("escrow/init_vault", vec![init_vault(&owner, &fctr_mint)], &[]).await;
+
+        let confidant = self.user(100 * ONE_FCTR).await;
+        let confidant_key = confidant.keypair.pubkey();
+        self.measure("escrow/stake", vec![stake(&confidant_key, &fctr_mint, &confidant.fctr_account, 0)], &[&confidant.keypair]).await;
+        self.run(vec![publish_offer(&confidant_key, 1, 500)], &[&confidant.keypair]).await;
+
+        let principal = self.user(10 * ONE_FCTR).await;
+        let principal_key = principal.keypair.pubkey();
+        self.measure("escrow/entrust", vec![entrust(&principal_key, &confidant_key, &fctr_mint, &principal.fctr_account, 5 * ONE_FCTR / 2, 0)], &[&principal.keypair]).await;
+        let principals = [PrincipalAccounts { principal: principal_key, fctr_account: principal.fctr_account, bcdev_account: principal.bcdev_account }];
+
+        self.advance_rounds(2).await;
+        let instruction = unstake(&confidant_key, &fctr_mint, &bcdev_mint, &confidant.fctr_account, &confidant.bcdev_account, &principals);
+        self.measure("escrow/unstake/1_principals", vec![instruction], &[&confidant.keypair]).await;
+    }
+
+    fn set_program_account(&mut self, address: &Pubkey, lamports: u64, data: Vec<u8>) {
+        let account = Account { lamports, data, owner: PROGRAM_ID, executable: false, rent_epoch: 0 };
+        self.context.set_account(address, &AccountSharedData::from(account));
+    }
+
+    // Accounts left behind by the pre-versioning program: the pool, a principal and a staked confidant holding its entrustment inline
+    async fn migrations(&mut self) {
+        let (fctr_mint, bcdev_mint, proof_signer) = (self.fctr_mint, self.bcdev_mint, self.proof_signer.pubkey());
+        let owner = self.context.payer.pubkey();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        let current_time = clock.unix_timestamp as u64;
+
+        let staking = staking_pda();
+        let staking_lamports = self.context.banks_client.get_balance(staking).await.unwrap();
+        self.set_program_account(&staking, staking_lamports, legacy_staking(&owner, ROUND_TIME, &fctr_mint, &bcdev_mint, &proof_signer));
+
+        let (principal, confidant) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let staker_info_lamports = rent.minimum_balance(8 + LEGACY_STAKER_INFO_LEN);
+        self.set_program_account(&staker_info_pda(&principal), staker_info_lamports, legacy_staker_info(&principal, 0, 0, &[]));
+        let legacy_confidant = legacy_staker_info(&confidant, 100 * ONE_FCTR, current_time, &[(principal, 5 * ONE_FCTR)]);
+        self.set_program_account(&staker_info_pda(&confidant), staker_info_lamports, legacy_confidant);
+
+        self.measure("migrate_staking", vec![migrate_staking(&owner)], &[]).await;
+        self.measure("migrate_staker_info/0_principals", vec![migrate_staker_info(&owner, &principal, &[])], &[]).await;
+        self.measure("migrate_staker_info/1_principals", vec![migrate_staker_info(&owner, &confidant, &[principal])], &[]).await;
+    }
+
+    // Takes over the figures measured by a bench that needed a pool of its own
+    fn merge(&mut self, other: Bench, prefixes: &[&str]) {
+        let measured = other.measured.into_iter().filter(|(name, _)| prefixes.iter().any(|prefix| name.starts_with(prefix)));
+        self.measured.extend(measured);
+    }
+}
+
+// Run with UPDATE_CU_REPORT=1 to record the current figures as the new baseline
+#[tokio::test]
+async fn compute_units() {
+    let mut bench = Bench::new().await;
+    for principals_count in PRINCIPAL_COUNTS {
+        bench.unstake_with_principals(principals_count).await;
+    }
+    bench.exit().await;
+    bench.stake_for_beneficiary().await;
+    bench.entrustments().await;
+    bench.liquid_staking().await;
+    bench.views().await;
+    bench.treasury().await;
+
+    let mut escrow_bench = Bench::new().await;
+    escrow_bench.escrow().await;
+    bench.merge(escrow_bench, &["escrow/"]);
+    let mut migration_bench = Bench::new().await;
+    migration_bench.migrations().await;
+    bench.merge(migration_bench, &["migrate_"]);
+
+    for (name, units) in &bench.measured {
+        println!("{name}: {units} CU");
+    }
+
+    let report_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("cu_report.json");
+    let mut report = Report::load(&report_path);
+    if std::env::var("UPDATE_CU_REPORT").is_ok() {
+        report.compute_units = bench.measured;
+        report.save(&report_path);
+        return;
+    }
+
+    let missing = report.missing(&bench.measured);
+    assert!(missing.is_empty(), "no baseline recorded for, run with UPDATE_CU_REPORT=1 to record one:\n{}", missing.join("\n"));
+    let regressions = report.regressions(&bench.measured);
+    assert!(regressions.is_empty(), "compute units grew by more than {}%:\n{}", report.threshold_percent, regressions.join("\n"));
+}
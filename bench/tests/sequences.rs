@@ -0,0 +1,440 @@
+// Random instruction sequences against the compiled program, checking after every step that the bookkeeping
+// of the fetched accounts still adds up. Unlike sim/tests/invariants.rs this goes through the real handlers
+// and account constraints, so it needs `anchor build` first like the compute unit bench.
+
+use proptest::prelude::*;
+use solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    clock::Clock,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use solana_staking_bench::*;
+
+const ROUND_TIME: u64 = 1;
+const USERS: usize = 6;
+// Users that publish an offer come first
+const CONFIDANTS: usize = 3;
+// More principals don't fit into the unstake transaction
+const MAX_PRINCIPALS: u64 = 4;
+const LOCK_TIERS_NUM: u8 = 4;
+const MAX_ENTRUST_TERM_ROUNDS: u64 = 12;
+
+// Amounts are fractions of what the user holds when the step runs, so that most of them get past the checks
+#[derive(Clone, Debug)]
+enum Step {
+    BuyFctr { user: usize, fctr: u64 },
+    Stake { user: usize, lock_tier: u8 },
+    Unstake { user: usize },
+    Entrust { user: usize, confidant: usize, fraction_bps: u64, term_rounds: u64 },
+    DemandBack { user: usize, confidant: usize },
+    SellFctr { user: usize, fraction_bps: u64 },
+    SellBcdev { user: usize, fraction_bps: u64 },
+    Warp { rounds: u64 }
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    let user = 0..USERS;
+    prop_oneof![
+        3 => (user.clone(), 10..=1_000u64).prop_map(|(user, fctr)| Step::BuyFctr { user, fctr }),
+        2 => (user.clone(), 0..LOCK_TIERS_NUM).prop_map(|(user, lock_tier)| Step::Stake { user, lock_tier }),
+        2 => user.clone().prop_map(|user| Step::Unstake { user }),
+        4 => (user.clone(), 0..CONFIDANTS, 2_500..=5_000u64, 0..=MAX_ENTRUST_TERM_ROUNDS)
+            .prop_map(|(user, confidant, fraction_bps, term_rounds)| Step::Entrust { user, confidant, fraction_bps, term_rounds }),
+        2 => (user.clone(), 0..CONFIDANTS).prop_map(|(user, confidant)| Step::DemandBack { user, confidant }),
+        1 => (user.clone(), 1..=12_000u64).prop_map(|(user, fraction_bps)| Step::SellFctr { user, fraction_bps }),
+        1 => (user, 1..=12_000u64).prop_map(|(user, fraction_bps)| Step::SellBcdev { user, fraction_bps }),
+        2 => (1..=15u64).prop_map(|rounds| Step::Warp { rounds })
+    ]
+}
+
+struct User {
+    keypair: Keypair,
+    fctr_account: Pubkey,
+    bcdev_account: Pubkey,
+}
+
+// The fields of Staking the invariants are about
+struct StakingTotals {
+    total_fctr_bought_by_users: u64,
+    total_fctr_sold_by_users: u64,
+    total_bcdev_sold_by_users: u64,
+    total_staked: u64,
+    active_stakers: u64,
+    total_bcdev_minted: u64,
+    total_entrusted_fctr: u64,
+    total_sol_in: u64,
+    total_sol_out: u64,
+}
+
+impl StakingTotals {
+    fn decode(data: &[u8]) -> Self {
+        let mut fields = Fields::new(data);
+        // owner, round_time, rounds_num
+        fields.skip(32 + 8 * 2);
+        let total_fctr_bought_by_users = fields.u64();
+        let total_fctr_sold_by_users = fields.u64();
+        let total_bcdev_sold_by_users = fields.u64();
+        // finished, finish_time, the mints and proof signer, bump, lock_tiers, the early exit settings
+        fields.skip(1 + 8 + 32 * 3 + 1 + 16 * LOCK_TIERS_NUM as usize + 1 + 8 + 1 + 1);
+        let total_staked = fields.u64();
+        // bonus per share, treasury, escrow_mode, fctr_vault, sfctr_mint, the liquid stake, entrust_bounds, arbiter, version
+        fields.skip(16 * 2 + 8 * 2 + 1 + 32 * 2 + 8 * 3 + 8 * 4 + 32 + 1);
+        let active_stakers = fields.u64();
+        // registered_users
+        fields.skip(8);
+        StakingTotals {
+            total_fctr_bought_by_users,
+            total_fctr_sold_by_users,
+            total_bcdev_sold_by_users,
+            total_staked,
+            active_stakers,
+            total_bcdev_minted: fields.u64(),
+            total_entrusted_fctr: fields.u64(),
+            total_sol_in: fields.u64(),
+            total_sol_out: fields.u64(),
+        }
+    }
+}
+
+// The fields of StakerInfo the invariants are about
+struct Position {
+    stake_size: u64,
+    ftcr_amount: u64,
+    user_rpr: u64,
+    is_staked: bool,
+    is_in_trust_program: bool,
+    principals_count: u64,
+    entrusted_fctr: u64,
+    entrustments_count: u64,
+}
+
+impl Position {
+    fn decode(data: &[u8]) -> Self {
+        let mut fields = Fields::new(data);
+        fields.skip(32);
+        let stake_size = fields.u64();
+        fields.skip(8);
+        let ftcr_amount = fields.u64();
+        // bcdev_amount, pending_bcdev_reward, last_update_timestamp
+        fields.skip(8 * 3);
+        let user_rpr = fields.u64();
+        // bought_fctr, entrusted_tokens
+        fields.skip(8 + 1);
+        let is_staked = fields.bool();
+        let is_in_trust_program = fields.bool();
+        // lock_tier, lock_end, bonus debts
+        fields.skip(1 + 8 + 16 * 2);
+        Position {
+            stake_size,
+            ftcr_amount,
+            user_rpr,
+            is_staked,
+            is_in_trust_program,
+            principals_count: fields.u64(),
+            entrusted_fctr: fields.u64(),
+            entrustments_count: fields.u64(),
+        }
+    }
+}
+
+struct Entrustment {
+    confidant: usize,
+    principal: usize,
+    amount: u64,
+    rpr_boost: u64,
+    term_end: u64,
+}
+
+impl Entrustment {
+    fn decode(confidant: usize, principal: usize, data: &[u8]) -> Self {
+        let mut fields = Fields::new(data);
+        fields.skip(32 * 2);
+        let amount = fields.u64();
+        fields.skip(8);
+        let rpr_boost = fields.u64();
+        fields.skip(8);
+        Entrustment { confidant, principal, amount, rpr_boost, term_end: fields.u64() }
+    }
+}
+
+struct Pool {
+    context: ProgramTestContext,
+    fctr_mint: Pubkey,
+    bcdev_mint: Pubkey,
+    service_fctr_account: Pubkey,
+    service_bcdev_account: Pubkey,
+    users: Vec<User>,
+    // Makes every transaction unique, a repeated step would otherwise be dropped as already processed
+    nonce: u64,
+}
+
+impl Pool {
+    async fn new() -> Self {
+        if std::env::var("SBF_OUT_DIR").is_err() && std::env::var("BPF_OUT_DIR").is_err() {
+            std::env::set_var("SBF_OUT_DIR", "../target/deploy");
+        }
+        let mut program_test = ProgramTest::new("solana_staking", PROGRAM_ID, None);
+        program_test.prefer_bpf(true);
+
+        let mut pool = Pool {
+            context: program_test.start_with_context().await,
+            fctr_mint: Pubkey::default(),
+            bcdev_mint: Pubkey::default(),
+            service_fctr_account: Pubkey::default(),
+            service_bcdev_account: Pubkey::default(),
+            users: vec![],
+            nonce: 0,
+        };
+        pool.fctr_mint = pool.create_mint(12).await;
+        pool.bcdev_mint = pool.create_mint(18).await;
+        let (fctr_mint, bcdev_mint) = (pool.fctr_mint, pool.bcdev_mint);
+        pool.service_fctr_account = pool.create_token_account(&fctr_mint, &staking_pda()).await;
+        pool.service_bcdev_account = pool.create_token_account(&bcdev_mint, &staking_pda()).await;
+
+        let owner = pool.context.payer.pubkey();
+        let proof_signer = Keypair::new();
+        pool.must_run(vec![initialize(&owner, ROUND_TIME, &fctr_mint, &bcdev_mint, &proof_signer.pubkey())], &[]).await;
+        // Wide deposit ratio so that principals aren't held back by what their confidant holds
+        pool.must_run(vec![set_entrust_bounds(&owner, 2500, 5000, 0, 1_000_000)], &[]).await;
+        pool.must_run(vec![fund(&owner, 100 * LAMPORTS_PER_SOL)], &[]).await;
+
+        for index in 0..USERS {
+            let keypair = Keypair::new();
+            pool.must_run(vec![system_instruction::transfer(&owner, &keypair.pubkey(), 100 * LAMPORTS_PER_SOL)], &[]).await;
+            let fctr_account = pool.create_token_account(&fctr_mint, &keypair.pubkey()).await;
+            let bcdev_account = pool.create_token_account(&bcdev_mint, &keypair.pubkey()).await;
+            pool.must_run(vec![register(&keypair.pubkey(), &proof_signer.pubkey())], &[&keypair, &proof_signer]).await;
+            if index < CONFIDANTS {
+                pool.must_run(vec![publish_offer(&keypair.pubkey(), MAX_PRINCIPALS, 500)], &[&keypair]).await;
+            }
+            pool.users.push(User { keypair, fctr_account, bcdev_account });
+        }
+        pool
+    }
+
+    // Rejections by the program are expected for random steps, anything else fails the run
+    async fn run(&mut self, instructions: Vec<Instruction>, signers: &[&Keypair]) -> bool {
+        self.nonce += 1;
+        let mut all_instructions = vec![ComputeBudgetInstruction::set_compute_unit_price(self.nonce)];
+        all_instructions.extend(instructions);
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend(signers);
+        let transaction = Transaction::new_signed_with_payer(&all_instructions, Some(&self.context.payer.pubkey()), &all_signers, blockhash);
+
+        match self.context.banks_client.process_transaction(transaction).await {
+            Ok(()) => true,
+            Err(BanksClientError::TransactionError(_)) => false,
+            Err(err) => panic!("{err:?}"),
+        }
+    }
+
+    async fn must_run(&mut self, instructions: Vec<Instruction>, signers: &[&Keypair]) {
+        assert!(self.run(instructions, signers).await, "setup transaction failed");
+    }
+
+    async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.context.payer.pubkey();
+        self.must_run(vec![
+            system_instruction::create_account(&payer, &mint.pubkey(), rent.minimum_balance(spl_token::state::Mint::LEN), spl_token::state::Mint::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &staking_pda(), None, decimals).unwrap(),
+        ], &[&mint]).await;
+        mint.pubkey()
+    }
+
+    async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let payer = self.context.payer.pubkey();
+        self.must_run(vec![
+            system_instruction::create_account(&payer, &account.pubkey(), rent.minimum_balance(spl_token::state::Account::LEN), spl_token::state::Account::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), mint, owner).unwrap(),
+        ], &[&account]).await;
+        account.pubkey()
+    }
+
+    async fn account_data(&mut self, address: Pubkey) -> Option<Vec<u8>> {
+        self.context.banks_client.get_account(address).await.unwrap().map(|account| account.data)
+    }
+
+    async fn token_balance(&mut self, account: Pubkey) -> u64 {
+        self.context.banks_client.get_packed_account_data::<spl_token::state::Account>(account).await.unwrap().amount
+    }
+
+    async fn mint_supply(&mut self, mint: Pubkey) -> u64 {
+        self.context.banks_client.get_packed_account_data::<spl_token::state::Mint>(mint).await.unwrap().supply
+    }
+
+    async fn now(&mut self) -> u64 {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp as u64
+    }
+
+    async fn staking(&mut self) -> StakingTotals {
+        StakingTotals::decode(&self.account_data(staking_pda()).await.unwrap())
+    }
+
+    async fn position(&mut self, user: usize) -> Position {
+        let address = staker_info_pda(&self.users[user].keypair.pubkey());
+        Position::decode(&self.account_data(address).await.unwrap())
+    }
+
+    // Every open entrust record between a confidant and another user
+    async fn entrustments(&mut self) -> Vec<Entrustment> {
+        let mut entrustments = vec![];
+        for confidant in 0..CONFIDANTS {
+            for principal in 0..USERS {
+                let address = entrust_record_pda(&self.users[confidant].keypair.pubkey(), &self.users[principal].keypair.pubkey());
+                if let Some(data) = self.account_data(address).await.filter(|data| !data.is_empty()) {
+                    entrustments.push(Entrustment::decode(confidant, principal, &data));
+                }
+            }
+        }
+        entrustments
+    }
+
+    fn principal_accounts(&self, principal: usize) -> PrincipalAccounts {
+        let user = &self.users[principal];
+        PrincipalAccounts { principal: user.keypair.pubkey(), fctr_account: user.fctr_account, bcdev_account: user.bcdev_account }
+    }
+
+    async fn apply(&mut self, step: &Step) -> Result<(), TestCaseError> {
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let (user, instruction) = match *step {
+            Step::BuyFctr { user, fctr } => {
+                let User { keypair, fctr_account, .. } = &self.users[user];
+                (user, buy_fctr(&keypair.pubkey(), &fctr_mint, fctr_account, fctr * ONE_FCTR))
+            },
+            Step::Stake { user, lock_tier } => {
+                let User { keypair, fctr_account, .. } = &self.users[user];
+                (user, stake(&keypair.pubkey(), &fctr_mint, fctr_account, lock_tier))
+            },
+            Step::Unstake { user } => {
+                // A confidant settles all of its principals on the way out
+                let principals: Vec<_> = self.entrustments().await.iter()
+                    .filter(|e| e.confidant == user)
+                    .map(|e| self.principal_accounts(e.principal))
+                    .collect();
+                let User { keypair, fctr_account, bcdev_account } = &self.users[user];
+                (user, unstake(&keypair.pubkey(), &fctr_mint, &bcdev_mint, fctr_account, bcdev_account, &principals))
+            },
+            Step::Entrust { user, confidant, fraction_bps, term_rounds } => {
+                let amount = (self.position(user).await.ftcr_amount as u128 * fraction_bps as u128 / 10_000) as u64;
+                let confidant = self.users[confidant].keypair.pubkey();
+                let User { keypair, fctr_account, .. } = &self.users[user];
+                (user, entrust(&keypair.pubkey(), &confidant, &fctr_mint, fctr_account, amount, term_rounds))
+            },
+            Step::DemandBack { user, confidant } => {
+                let term_end = self.entrustments().await.iter().find(|e| e.confidant == confidant && e.principal == user).map(|e| e.term_end);
+                let confidant_key = self.users[confidant].keypair.pubkey();
+                let principal = self.principal_accounts(user);
+                let keypair = self.users[user].keypair.insecure_clone();
+                let returned = self.run(vec![demand_back(&principal.principal, &confidant_key, &fctr_mint, &bcdev_mint, &principal)], &[&keypair]).await;
+
+                // Whatever the confidant did in the meantime, the principal gets their FCTR back once the term is over
+                if let Some(term_end) = term_end {
+                    let now = self.now().await;
+                    prop_assert!(now < term_end || returned, "demand_back failed after the term");
+                }
+                return Ok(());
+            },
+            Step::SellFctr { user, fraction_bps } => {
+                let fctr_account = self.users[user].fctr_account;
+                let amount = (self.token_balance(fctr_account).await as u128 * fraction_bps as u128 / 10_000) as u64;
+                let keypair = &self.users[user].keypair;
+                (user, sell_fctr(&keypair.pubkey(), &fctr_mint, &self.service_fctr_account, &fctr_account, amount))
+            },
+            Step::SellBcdev { user, fraction_bps } => {
+                let bcdev_account = self.users[user].bcdev_account;
+                let amount = (self.token_balance(bcdev_account).await as u128 * fraction_bps as u128 / 10_000) as u64;
+                let keypair = &self.users[user].keypair;
+                (user, sell_bcdev(&keypair.pubkey(), &bcdev_mint, &self.service_bcdev_account, &bcdev_account, amount))
+            },
+            Step::Warp { rounds } => {
+                let mut clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+                clock.unix_timestamp += (rounds * ROUND_TIME) as i64;
+                self.context.set_sysvar(&clock);
+                return Ok(());
+            }
+        };
+        let keypair = self.users[user].keypair.insecure_clone();
+        self.run(vec![instruction], &[&keypair]).await;
+        Ok(())
+    }
+
+    async fn check_invariants(&mut self) -> Result<(), TestCaseError> {
+        let staking = self.staking().await;
+        let entrustments = self.entrustments().await;
+        let mut positions = vec![];
+        for user in 0..USERS {
+            positions.push(self.position(user).await);
+        }
+
+        let staking_account = self.context.banks_client.get_account(staking_pda()).await.unwrap().unwrap();
+        let rent = self.context.banks_client.get_rent().await.unwrap().minimum_balance(staking_account.data.len());
+        prop_assert_eq!(staking_account.lamports - rent, staking.total_sol_in - staking.total_sol_out, "reserve doesn't match the SOL totals");
+
+        let (fctr_mint, bcdev_mint) = (self.fctr_mint, self.bcdev_mint);
+        let bcdev_supply = self.mint_supply(bcdev_mint).await;
+        prop_assert_eq!(bcdev_supply, staking.total_bcdev_minted - staking.total_bcdev_sold_by_users, "BCDEV supply doesn't match the totals");
+
+        let stakers = positions.iter().filter(|position| position.is_staked);
+        prop_assert_eq!(staking.total_staked, stakers.clone().map(|position| position.stake_size).sum::<u64>(), "total_staked doesn't match the stakes");
+        prop_assert_eq!(staking.active_stakers, stakers.count() as u64, "active_stakers doesn't match the stakers");
+        prop_assert_eq!(staking.total_entrusted_fctr, entrustments.iter().map(|e| e.amount).sum::<u64>(), "total_entrusted_fctr doesn't match the records");
+
+        // Staked and entrusted FCTR is burnt, so every FCTR bought and not sold back is either in a token account,
+        // staked or in custody for an unstaked confidant
+        let fctr_supply = self.mint_supply(fctr_mint).await;
+        let staked: u64 = positions.iter().map(|position| position.stake_size).sum();
+        let custodied: u64 = entrustments.iter().filter(|e| !positions[e.confidant].is_staked).map(|e| e.amount).sum();
+        prop_assert_eq!(staking.total_fctr_bought_by_users - staking.total_fctr_sold_by_users, fctr_supply + staked + custodied, "FCTR went missing");
+
+        for (index, position) in positions.iter().enumerate() {
+            let as_confidant = entrustments.iter().filter(|e| e.confidant == index);
+            let as_principal = entrustments.iter().filter(|e| e.principal == index);
+
+            prop_assert_eq!(position.principals_count, as_confidant.clone().count() as u64, "principals_count of user {}", index);
+            prop_assert_eq!(position.entrusted_fctr, as_confidant.map(|e| e.amount).sum::<u64>(), "entrusted_fctr of user {}", index);
+            prop_assert!(!position.is_staked || position.entrusted_fctr <= position.stake_size, "user {} holds more entrusted FCTR than their stake", index);
+            prop_assert_eq!(position.entrustments_count, as_principal.clone().count() as u64, "entrustments_count of user {}", index);
+            prop_assert_eq!(position.user_rpr, 1 + as_principal.map(|e| e.rpr_boost).sum::<u64>(), "user_rpr of user {}", index);
+            prop_assert_eq!(position.is_in_trust_program, position.principals_count > 0 || position.entrustments_count > 0, "trust status of user {}", index);
+            prop_assert!(position.is_staked || position.stake_size == 0, "user {} has a stake without being staked", index);
+        }
+        Ok(())
+    }
+}
+
+async fn run_sequence(steps: &[Step]) -> Result<(), TestCaseError> {
+    let mut pool = Pool::new().await;
+    for step in steps {
+        pool.apply(step).await?;
+        pool.check_invariants().await?;
+    }
+    Ok(())
+}
+
+// Every case starts a fresh program test, so fewer cases than proptest's default unless PROPTEST_CASES asks for more
+fn cases() -> u32 {
+    std::env::var("PROPTEST_CASES").ok().and_then(|cases| cases.parse().ok()).unwrap_or(32)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(cases()))]
+
+    #[test]
+    fn bookkeeping_holds(steps in prop::collection::vec(step(), 1..40)) {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(run_sequence(&steps))?;
+    }
+}
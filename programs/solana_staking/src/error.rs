@@ -38,6 +38,58 @@ pub enum StakingError {
     InvalidTokenAccountOwner,
     #[msg("Invalid principal info account")]
     InvalidPrincipalInfoAccount,
+    #[msg("Invalid lock tier")]
+    InvalidLockTier,
+    #[msg("Lock tier can't be lowered while staked")]
+    LockTierDowngrade,
+    #[msg("Stake is locked")]
+    StakeIsLocked,
+    #[msg("Invalid lock tiers table")]
+    InvalidLockTiers,
+    #[msg("Invalid early exit penalty")]
+    InvalidPenalty,
+    #[msg("Pool mode can't be switched while there are stakes or entrustments")]
+    CantSwitchPoolMode,
+    #[msg("Invalid sFCTR mint")]
+    InvalidSfctrMint,
+    #[msg("Liquid staking is not enabled")]
+    LiquidStakingDisabled,
+    #[msg("Invalid beneficiary")]
+    InvalidBeneficiary,
+    #[msg("Can't prolong the lock of someone else's stake")]
+    CantProlongForeignLock,
+    #[msg("Invalid entrust bounds")]
+    InvalidEntrustBounds,
+    #[msg("Invalid confidant offer")]
+    InvalidOffer,
+    #[msg("Confidant doesn't accept principals")]
+    OfferNotAccepting,
+    #[msg("Amount is below the confidant's minimum")]
+    BelowOfferMinimum,
+    #[msg("Invalid entrustment term")]
+    InvalidEntrustTerm,
+    #[msg("Entrustment term is not over")]
+    EntrustTermNotOver,
+    #[msg("Not the owner or the arbiter")]
+    NotTheArbiter,
+    #[msg("Invalid slash")]
+    InvalidSlash,
+    #[msg("Remaining accounts don't match the principals")]
+    InvalidRemainingAccounts,
+    #[msg("Account is already up to date")]
+    AlreadyMigrated,
+    #[msg("Staker info still holds a stake, rewards or entrustments")]
+    CantCloseStakerInfo,
+    #[msg("Invalid history account")]
+    InvalidHistoryAccount,
+    #[msg("Reward doesn't fit into u64")]
+    RewardOverflow,
+    #[msg("Invalid holder info account")]
+    InvalidHolderInfoAccount,
+    #[msg("Can't entrust to oneself")]
+    SelfEntrust,
+    #[msg("Can't open someone else's stake above the shortest lock tier")]
+    CantLockForeignStake,
 
 
 
@@ -0,0 +1,18 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct RewardSplit {
+    pub confidant: Pubkey,
+    pub principal: Pubkey,
+    pub principal_reward: u64,
+    pub confidant_commission: u64
+}
+
+#[event]
+pub struct BalanceDrift {
+    pub staker: Pubkey,
+    pub fctr_recorded: u64,
+    pub fctr_held: u64,
+    pub bcdev_recorded: u64,
+    pub bcdev_held: u64
+}
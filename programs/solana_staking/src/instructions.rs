@@ -1,7 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount, Mint};
 
-use crate::{state::{Staking, StakerInfo}};
+use crate::state::{Staking, StakerInfo, EntrustRecord, ConfidantOffer, StakerHistory};
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
@@ -13,6 +13,54 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>
 }
 
+#[derive(Accounts)]
+pub struct Configure<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    pub owner: Signer<'info>
+}
+
+#[derive(Accounts)]
+pub struct InitVault<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(init, payer=owner, seeds=[b"fctr-vault"], bump, token::mint=fctr_mint, token::authority=staking)]
+    pub fctr_vault: Account<'info, TokenAccount>,
+    pub fctr_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>
+}
+
+// Accounts created with an older layout don't deserialize into the current one, hence the unchecked accounts
+#[derive(Accounts)]
+pub struct MigrateStaking<'info> {
+    /// CHECK: owner and discriminator are checked once the account is grown
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>
+}
+
+#[derive(Accounts)]
+#[instruction(staker_address: Pubkey)]
+pub struct MigrateStakerInfo<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    /// CHECK: owner and discriminator are checked while decoding whichever layout the account has
+    #[account(mut, seeds=[b"staker-info", staker_address.as_ref()], bump)]
+    pub staker_info: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>
+}
+
 #[derive(Accounts)]
 pub struct Fund<'info> {
     #[account(mut, seeds=[b"staking"], bump)]
@@ -45,6 +93,27 @@ pub struct Register<'info> {
     pub system_program: Program<'info, System>
 }
 
+#[derive(Accounts)]
+pub struct CloseStakerInfo<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(mut, seeds=[b"staker-info", staker.key().as_ref()], bump, close=staker)]
+    pub staker_info: Account<'info, StakerInfo>
+}
+
+#[derive(Accounts)]
+pub struct InitHistory<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    #[account(seeds = [b"staker-info", staker.key().as_ref()], bump)]
+    pub staker_info: Account<'info, StakerInfo>,
+    #[account(init, payer=staker, space = 8 + StakerHistory::LEN, seeds = [b"history", staker.key().as_ref()], bump)]
+    pub history: Account<'info, StakerHistory>,
+    pub system_program: Program<'info, System>
+}
+
 #[derive(Accounts)]
 pub struct Stake<'info> {
     #[account(mut, seeds=[b"staking"], bump)]
@@ -55,10 +124,33 @@ pub struct Stake<'info> {
     pub staker_fctr_account: Account<'info, TokenAccount>,
     #[account(mut)]
     pub fctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
     pub staker: Signer<'info>,
     pub token_program: Program<'info, Token>
 }
 
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct StakeFor<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(mut, seeds=[b"staker-info", payer.key().as_ref()], bump)]
+    pub payer_info: Account<'info, StakerInfo>,
+    #[account(mut, seeds=[b"staker-info", beneficiary.as_ref()], bump)]
+    pub beneficiary_info: Account<'info, StakerInfo>,
+    #[account(mut, token::authority=payer, token::mint=fctr_mint)]
+    pub payer_fctr_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>
+}
+
 #[derive(Accounts)]
 pub struct Unstake<'info> {
     #[account(mut, seeds=[b"staking"], bump)]
@@ -74,10 +166,107 @@ pub struct Unstake<'info> {
     pub bcdev_mint: Account<'info, Mint>,
     #[account(mut)]
     pub fctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+    pub staker: Signer<'info>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct SettlePrincipals<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(mut, seeds=[b"staker-info", confidant.key().as_ref()], bump)]
+    pub confidant_info: Account<'info, StakerInfo>,
+
+    #[account(mut)]
+    pub bcdev_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+    pub confidant: Signer<'info>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    pub owner: Signer<'info>,
+
+    #[account(mut, token::authority=owner, token::mint=fctr_mint)]
+    pub owner_fctr_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority=owner, token::mint=bcdev_mint)]
+    pub owner_bcdev_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub bcdev_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct SetSfctrMint<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    pub sfctr_mint: Account<'info, Mint>,
+    pub owner: Signer<'info>
+}
+
+#[derive(Accounts)]
+pub struct StakeLiquid<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(mut, seeds=[b"staker-info", staker.key().as_ref()], bump)]
+    pub staker_info: Account<'info, StakerInfo>,
+    #[account(mut, token::authority=staker, token::mint=fctr_mint)]
+    pub staker_fctr_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority=staker, token::mint=sfctr_mint)]
+    pub staker_sfctr_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub sfctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
     pub staker: Signer<'info>,
     pub token_program: Program<'info, Token>
 }
 
+#[derive(Accounts)]
+pub struct RedeemSfctr<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+    #[account(mut, token::authority=holder, token::mint=sfctr_mint)]
+    pub holder_sfctr_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority=holder, token::mint=fctr_mint)]
+    pub holder_fctr_account: Account<'info, TokenAccount>,
+    #[account(mut, token::authority=holder, token::mint=bcdev_mint)]
+    pub holder_bcdev_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub bcdev_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub sfctr_mint: Account<'info, Mint>,
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+    pub holder: Signer<'info>,
+    pub token_program: Program<'info, Token>
+}
+
 #[derive(Accounts)]
 pub struct BuyFctr<'info> {
     #[account(mut, seeds=[b"staking"], bump)]
@@ -147,10 +336,61 @@ pub struct SellBcdev<'info> {
     pub system_program: Program<'info, System>
 }
 
+#[derive(Accounts)]
+pub struct Quote {}
+
+#[derive(Accounts)]
+#[instruction(staker_address: Pubkey)]
+pub struct PendingRewards<'info> {
+    #[account(seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    #[account(seeds=[b"staker-info", staker_address.as_ref()], bump)]
+    pub staker_info: Account<'info, StakerInfo>
+}
+
+// Anyone may reconcile a staker's balances, it only ever brings the recorded amounts down to what the staker holds
+#[derive(Accounts)]
+#[instruction(staker_address: Pubkey)]
+pub struct SyncBalances<'info> {
+    #[account(seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    #[account(mut, seeds=[b"staker-info", staker_address.as_ref()], bump)]
+    pub staker_info: Account<'info, StakerInfo>,
+
+    #[account(token::authority=staker_address)]
+    pub staker_fctr_account: Account<'info, TokenAccount>,
+
+    #[account(token::authority=staker_address)]
+    pub staker_bcdev_account: Account<'info, TokenAccount>
+}
+
+#[derive(Accounts)]
+pub struct PublishOffer<'info> {
+    #[account(seeds=[b"staker-info", confidant.key().as_ref()], bump)]
+    pub confidant_info: Account<'info, StakerInfo>,
+
+    #[account(init, payer=confidant, space = 8 + ConfidantOffer::LEN, seeds=[b"confidant-offer", confidant.key().as_ref()], bump)]
+    pub confidant_offer: Account<'info, ConfidantOffer>,
+
+    #[account(mut)]
+    pub confidant: Signer<'info>,
+    pub system_program: Program<'info, System>
+}
+
+#[derive(Accounts)]
+pub struct UpdateOffer<'info> {
+    #[account(mut, seeds=[b"confidant-offer", confidant.key().as_ref()], bump=confidant_offer.bump)]
+    pub confidant_offer: Account<'info, ConfidantOffer>,
+
+    pub confidant: Signer<'info>
+}
+
 #[derive(Accounts)]
 #[instruction(confidant_address: Pubkey)]
 pub struct Entrust<'info> {
-    #[account(seeds=[b"staking"], bump)]
+    #[account(mut, seeds=[b"staking"], bump)]
     pub staking: Account<'info, Staking>,
 
     #[account(mut)]
@@ -162,12 +402,22 @@ pub struct Entrust<'info> {
     #[account(mut, seeds=[b"staker-info", confidant_address.as_ref()], bump)]
     pub confidant_info: Account<'info, StakerInfo>,
 
+    #[account(seeds=[b"confidant-offer", confidant_address.as_ref()], bump=confidant_offer.bump)]
+    pub confidant_offer: Account<'info, ConfidantOffer>,
+
+    #[account(init, payer=principal, space = 8 + EntrustRecord::LEN, seeds=[b"entrust", confidant_address.as_ref(), principal.key().as_ref()], bump)]
+    pub entrust_record: Account<'info, EntrustRecord>,
+
     #[account(mut)]
     pub fctr_mint: Account<'info, Mint>,
 
     #[account(mut, token::authority=principal, token::mint=fctr_mint)]
     pub principal_fctr_account: Account<'info, TokenAccount>,
 
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>
 }
@@ -187,16 +437,102 @@ pub struct DemandBack<'info> {
     #[account(mut, seeds=[b"staker-info", confidant_address.as_ref()], bump)]
     pub confidant_info: Account<'info, StakerInfo>,
 
+    #[account(mut, seeds=[b"entrust", confidant_address.as_ref(), principal.key().as_ref()], bump=entrust_record.bump, close=principal)]
+    pub entrust_record: Account<'info, EntrustRecord>,
+
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+
+    #[account(mut, token::authority=principal, token::mint=fctr_mint)]
+    pub principal_fctr_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub bcdev_mint: Account<'info, Mint>,
+
+    #[account(mut, token::authority=principal, token::mint=bcdev_mint)]
+    pub principal_bcdev_account: Account<'info, TokenAccount>,
+
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>
+}
+
+#[derive(Accounts)]
+#[instruction(principal_address: Pubkey)]
+pub struct ReleasePrincipal<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    #[account(mut)]
+    pub confidant: Signer<'info>,
+
+    /// CHECK: principal wallet, only receives the record rent back
+    #[account(mut, address=principal_address)]
+    pub principal: UncheckedAccount<'info>,
+
+    #[account(mut, seeds=[b"staker-info", principal_address.as_ref()], bump)]
+    pub principal_info: Account<'info, StakerInfo>,
+
+    #[account(mut, seeds=[b"staker-info", confidant.key().as_ref()], bump)]
+    pub confidant_info: Account<'info, StakerInfo>,
+
+    #[account(mut, seeds=[b"entrust", confidant.key().as_ref(), principal_address.as_ref()], bump=entrust_record.bump, close=principal)]
+    pub entrust_record: Account<'info, EntrustRecord>,
+
     #[account(mut)]
     pub fctr_mint: Account<'info, Mint>,
 
     #[account(mut, token::authority=principal, token::mint=fctr_mint)]
     pub principal_fctr_account: Account<'info, TokenAccount>,
 
+    #[account(mut)]
+    pub bcdev_mint: Account<'info, Mint>,
+
+    #[account(mut, token::authority=principal, token::mint=bcdev_mint)]
+    pub principal_bcdev_account: Account<'info, TokenAccount>,
+
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>
 }
 
+#[derive(Accounts)]
+#[instruction(confidant_address: Pubkey, principal_address: Pubkey)]
+pub struct SlashConfidant<'info> {
+    #[account(mut, seeds=[b"staking"], bump)]
+    pub staking: Account<'info, Staking>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut, seeds=[b"staker-info", confidant_address.as_ref()], bump)]
+    pub confidant_info: Account<'info, StakerInfo>,
+
+    #[account(mut, seeds=[b"staker-info", principal_address.as_ref()], bump)]
+    pub principal_info: Account<'info, StakerInfo>,
+
+    // Only principals currently entrusting to the confidant can be compensated
+    #[account(seeds=[b"entrust", confidant_address.as_ref(), principal_address.as_ref()], bump=entrust_record.bump)]
+    pub entrust_record: Account<'info, EntrustRecord>,
+
+    #[account(mut)]
+    pub fctr_mint: Account<'info, Mint>,
+
+    #[account(mut, token::authority=principal_address, token::mint=fctr_mint)]
+    pub principal_fctr_account: Account<'info, TokenAccount>,
+
+    /// CHECK: FCTR vault, only used when the pool is in escrow mode
+    #[account(mut, seeds=[b"fctr-vault"], bump)]
+    pub fctr_vault: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>
+}
+
 #[derive(Accounts)]
 pub struct Stop<'info>{
     #[account(mut, seeds=[b"staking"], bump)]
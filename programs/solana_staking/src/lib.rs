@@ -1,11 +1,17 @@
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
 
 pub mod state;
 pub mod instructions;
 pub mod error;
+pub mod math;
+pub mod utils;
+pub mod events;
 
 use instructions::*;
 use error::StakingError;
+use state::{LockTier, PenaltySource, PenaltyDestination, EntrustBounds};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -13,16 +19,17 @@ declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 pub mod solana_staking {    
     use std::cmp::min;
 
-    use anchor_lang::solana_program::{native_token::LAMPORTS_PER_SOL, system_instruction, program::invoke};
-    use anchor_spl::token::{self, MintTo, Burn, TokenAccount};
+    use anchor_lang::solana_program::{system_instruction, program::invoke, program_option::COption};
+    use anchor_lang::AccountsClose;
+    use anchor_spl::token::{self, MintTo, Burn};
 
-    use crate::state::StakerInfo;
+    use crate::state::{Staking, StakerInfo, LegacyStakerInfo, HistoryKind, STAKING_VERSION, STAKER_INFO_VERSION, PRINCIPAL_ACCOUNTS_NUM, LOCK_TIERS_NUM, DEFAULT_LOCK_TIERS, DEFAULT_ENTRUST_BOUNDS};
+    use crate::math::{self, MULTIPLIER_PRECISION, BPS_DENOMINATOR};
+    use crate::utils::{take_fctr, give_fctr, grow_account, record_history, return_entrustment, adopt_legacy_entrustment, PrincipalAccounts};
+    use crate::events::{RewardSplit, BalanceDrift};
 
     use super::*;
 
-    const ONE_FCTR: u64 = 10_u64.pow(12);
-    const ONE_BCDEV: u64 = 10_u64.pow(18);
-
     pub fn initialize(ctx: Context<Initialize>, round_time: u64, fctr_mint: Pubkey, bcdev_mint: Pubkey, proof_signer: Pubkey) -> Result<()> {
         let staking = &mut ctx.accounts.staking;
         staking.round_time = round_time;
@@ -31,9 +38,153 @@ pub mod solana_staking {
         staking.fctr_mint = fctr_mint;
         staking.bcdev_mint = bcdev_mint;
         staking.proof_signer = proof_signer;
+        staking.lock_tiers = DEFAULT_LOCK_TIERS;
+        staking.entrust_bounds = DEFAULT_ENTRUST_BOUNDS;
+        staking.version = STAKING_VERSION;
         Ok(())
     }
 
+    pub fn set_lock_tiers(ctx: Context<Configure>, lock_tiers: [LockTier; 4]) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+
+        for (i, tier) in lock_tiers.iter().enumerate() {
+            require!(tier.rounds > 0 && tier.multiplier >= MULTIPLIER_PRECISION, StakingError::InvalidLockTiers);
+            if i > 0 {
+                require!(tier.rounds > lock_tiers[i - 1].rounds && tier.multiplier >= lock_tiers[i - 1].multiplier, StakingError::InvalidLockTiers);
+            }
+        }
+
+        staking.lock_tiers = lock_tiers;
+        Ok(())
+    }
+
+    pub fn set_early_exit(ctx: Context<Configure>, enabled: bool, penalty_bps: u64, source: PenaltySource, destination: PenaltyDestination) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(penalty_bps <= BPS_DENOMINATOR, StakingError::InvalidPenalty);
+
+        staking.early_exit_enabled = enabled;
+        staking.early_exit_penalty_bps = penalty_bps;
+        staking.early_exit_penalty_source = source;
+        staking.early_exit_penalty_destination = destination;
+        Ok(())
+    }
+
+    pub fn set_entrust_bounds(ctx: Context<Configure>, bounds: EntrustBounds) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(bounds.min_fraction_bps <= bounds.max_fraction_bps && bounds.max_fraction_bps <= BPS_DENOMINATOR, StakingError::InvalidEntrustBounds);
+        require!(bounds.min_deposit_ratio_bps <= bounds.max_deposit_ratio_bps, StakingError::InvalidEntrustBounds);
+
+        staking.entrust_bounds = bounds;
+        Ok(())
+    }
+
+    pub fn set_arbiter(ctx: Context<Configure>, arbiter: Pubkey) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+
+        staking.arbiter = arbiter;
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+
+        let staking_bump = staking.bump.to_le_bytes();
+        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        give_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(),
+            ctx.accounts.fctr_vault.to_account_info(), ctx.accounts.owner_fctr_account.to_account_info(), staking.treasury_fctr
+        )?;
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(), 
+            MintTo { mint: ctx.accounts.bcdev_mint.to_account_info(), to: ctx.accounts.owner_bcdev_account.to_account_info(), authority: staking.to_account_info() }, 
+            &signer_seeds
+        );
+        token::mint_to(cpi_ctx, staking.treasury_bcdev)?;
+        staking.total_bcdev_minted = staking.total_bcdev_minted.saturating_add(staking.treasury_bcdev);
+
+        staking.treasury_fctr = 0;
+        staking.treasury_bcdev = 0;
+        Ok(())
+    }
+
+    pub fn init_vault(ctx: Context<InitVault>) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        // Stakes and entrustments taken in burn mode have no tokens in the vault to be returned from
+        require!(staking.total_staked == 0 && staking.liquid_fctr == 0 && staking.treasury_fctr == 0, StakingError::CantSwitchPoolMode);
+        require!(staking.total_entrusted_fctr == 0, StakingError::CantSwitchPoolMode);
+
+        staking.escrow_mode = true;
+        staking.fctr_vault = ctx.accounts.fctr_vault.key();
+        Ok(())
+    }
+
+    pub fn set_sfctr_mint(ctx: Context<SetSfctrMint>) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        let sfctr_mint = &ctx.accounts.sfctr_mint;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(staking.sfctr_mint == Pubkey::default(), StakingError::InvalidSfctrMint);
+        require!(sfctr_mint.mint_authority == COption::Some(staking.key()) && sfctr_mint.supply == 0, StakingError::InvalidSfctrMint);
+
+        staking.sfctr_mint = sfctr_mint.key();
+        Ok(())
+    }
+
+    pub fn migrate_staking(ctx: Context<MigrateStaking>) -> Result<()> {
+        let staking_account = ctx.accounts.staking.to_account_info();
+        grow_account(&staking_account, ctx.accounts.owner.to_account_info(), 8 + Staking::LEN)?;
+
+        let mut staking = Account::<Staking>::try_from(&staking_account)?;
+        require!(ctx.accounts.owner.key() == staking.owner, StakingError::NotTheOwner);
+        require!(staking.version < STAKING_VERSION, StakingError::AlreadyMigrated);
+
+        staking.migrate();
+        staking.exit(ctx.program_id)
+    }
+
+    // Anyone may pay for bringing a staker's account up to date. Accounts from before versioning are shorter and keep their principals inline,
+    // each open one takes [entrust record, principal staker info] in the remaining accounts
+    pub fn migrate_staker_info<'info>(ctx: Context<'_, '_, '_, 'info, MigrateStakerInfo<'info>>, _staker: Pubkey) -> Result<()> {
+        let staker_info_account = ctx.accounts.staker_info.to_account_info();
+        let staking = &mut ctx.accounts.staking;
+
+        let (mut staker_info, from_version, legacy_principals) = if staker_info_account.data_len() < 8 + StakerInfo::LEN {
+            let legacy = LegacyStakerInfo::try_from_account(&staker_info_account)?;
+            let mut staker_info = StakerInfo::from_legacy(&legacy);
+            // The stake didn't share in penalties distributed before it got migrated
+            staker_info.sync_bonus_debt(staking);
+            (staker_info, 0, legacy.open_principals())
+        } else {
+            let staker_info = Account::<StakerInfo>::try_from(&staker_info_account)?.into_inner();
+            let version = staker_info.version;
+            require!(version < STAKER_INFO_VERSION, StakingError::AlreadyMigrated);
+            (staker_info, version, Vec::new())
+        };
+        require!(ctx.remaining_accounts.len() == legacy_principals.len() * 2, StakingError::InvalidRemainingAccounts);
+
+        grow_account(&staker_info_account, ctx.accounts.payer.to_account_info(), 8 + StakerInfo::LEN)?;
+        // Adopted entrustments add to total_entrusted_fctr themselves
+        staker_info.backfill_counters(staking, from_version);
+        for (legacy, accounts) in legacy_principals.iter().zip(ctx.remaining_accounts.chunks_exact(2)) {
+            adopt_legacy_entrustment(staking, &mut staker_info, legacy, &accounts[0], &accounts[1], ctx.accounts.payer.to_account_info(), ctx.program_id)?;
+        }
+
+        staker_info.version = STAKER_INFO_VERSION;
+        let mut data = staker_info_account.try_borrow_mut_data()?;
+        staker_info.try_serialize(&mut &mut data[..])
+    }
+
     pub fn fund(ctx: Context<Fund>, amount: u64) -> Result<()> {
         let staking = &mut ctx.accounts.staking;
         let donation_transfer_instruction = system_instruction::transfer(&ctx.accounts.owner.key(), &staking.key(), amount);
@@ -42,6 +193,7 @@ pub mod solana_staking {
             ctx.accounts.owner.to_account_info(),
             staking.to_account_info()
         ])?;
+        staking.total_sol_in += amount;
 
         Ok(())
     }
@@ -54,6 +206,7 @@ pub mod solana_staking {
 
         **staking.to_account_info().try_borrow_mut_lamports()? -= amount;
         **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? += amount;
+        staking.total_sol_out += amount;
 
         Ok(())
     }
@@ -67,44 +220,80 @@ pub mod solana_staking {
         staker_info.staker = ctx.accounts.staker.key();
         staker_info.user_rpr = 1;
         staker_info.bump = *ctx.bumps.get("staker_info").unwrap();
+        staker_info.version = STAKER_INFO_VERSION;
+        staking.registered_users += 1;
+        Ok(())
+    }
+
+    pub fn close_staker_info(ctx: Context<CloseStakerInfo>) -> Result<()> {
+        require!(ctx.accounts.staker_info.can_close(&ctx.accounts.staking), StakingError::CantCloseStakerInfo);
+
+        let staking = &mut ctx.accounts.staking;
+        staking.registered_users -= 1;
+        if ctx.accounts.staker_info.is_staked {
+            staking.active_stakers -= 1;
+        }
+        Ok(())
+    }
+
+    pub fn init_history(ctx: Context<InitHistory>) -> Result<()> {
+        let history = &mut ctx.accounts.history;
+        history.staker = ctx.accounts.staker.key();
+        history.bump = *ctx.bumps.get("history").unwrap();
         Ok(())
     }
 
-    pub fn stake(ctx: Context<Stake>) -> Result<()> {
+    pub fn stake(ctx: Context<Stake>, lock_tier: u8) -> Result<()> {
         let staking = &mut ctx.accounts.staking;
         let staker_info = &mut ctx.accounts.staker_info;
-        staker_info.is_staked = true;
 
         require!(!staking.finished, StakingError::StakingFinished);
         require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidTokenAccount);
         require!(ctx.accounts.staker_fctr_account.mint == staking.fctr_mint, StakingError::InvalidTokenAccount);
 
-        let staking_bump = staking.bump.to_le_bytes();
-        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
-        let signer_seeds = [&seeds[..]];
-        let amount = min(ctx.accounts.staker_fctr_account.amount, staker_info.ftcr_amount);
-
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(), 
-            Burn { mint: ctx.accounts.fctr_mint.to_account_info(), from: ctx.accounts.staker_fctr_account.to_account_info(), authority: ctx.accounts.staker.to_account_info() }, 
-            &signer_seeds
-        );
-        token::burn(cpi_ctx, amount)?;
-        
+        let amount = min(ctx.accounts.staker_fctr_account.amount, staker_info.spendable_fctr());
         let current_time = Clock::get().unwrap().unix_timestamp as u64;
-        if staker_info.last_update_timestamp > 0 {  // Already staked
-            let period = current_time - staker_info.last_update_timestamp;
-            staker_info.pending_bcdev_reward += period * staker_info.stake_size * staker_info.user_rpr;
-        }
 
-        staker_info.last_update_timestamp = current_time;
-        staker_info.stake_size += amount;
+        staker_info.add_stake(staking, lock_tier, amount, current_time)?;
         staker_info.ftcr_amount = 0;
+        record_history(ctx.remaining_accounts.last(), staker_info.staker, HistoryKind::Stake, amount, current_time, ctx.program_id)?;
 
-        Ok(())
+        take_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            ctx.accounts.staker_fctr_account.to_account_info(), ctx.accounts.staker.to_account_info(), amount
+        )
     }
 
-    pub fn unstake(ctx: Context<Unstake>) -> Result<()>{
+    pub fn stake_for(ctx: Context<StakeFor>, beneficiary: Pubkey, amount: u64, lock_tier: u8) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        let payer_info = &mut ctx.accounts.payer_info;
+        let beneficiary_info = &mut ctx.accounts.beneficiary_info;
+
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(beneficiary != ctx.accounts.payer.key(), StakingError::InvalidBeneficiary);
+        require!(amount > 0 && ctx.accounts.payer_fctr_account.amount >= amount && payer_info.spendable_fctr() >= amount, StakingError::NotEnoughTokens);
+        require!((lock_tier as usize) < LOCK_TIERS_NUM, StakingError::InvalidLockTier);
+
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        // A position can be topped up by anyone, but only its owner can change the tier or prolong the lock.
+        // Opening one locks up what the beneficiary has in custody too, so others may only open it on the shortest tier.
+        if beneficiary_info.is_staked {
+            require!(lock_tier == beneficiary_info.lock_tier, StakingError::CantProlongForeignLock);
+            beneficiary_info.top_up_stake(staking, amount, current_time)?;
+        } else {
+            require!(lock_tier == 0, StakingError::CantLockForeignStake);
+            beneficiary_info.add_stake(staking, lock_tier, amount, current_time)?;
+        }
+        payer_info.ftcr_amount -= amount;
+
+        take_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            ctx.accounts.payer_fctr_account.to_account_info(), ctx.accounts.payer.to_account_info(), amount
+        )
+    }
+
+    pub fn unstake<'info>(ctx: Context<'_, '_, '_, 'info, Unstake<'info>>) -> Result<()>{
         let staker_info = &mut ctx.accounts.staker_info;
         let staking = &mut ctx.accounts.staking;
         let current_time = Clock::get().unwrap().unix_timestamp as u64;
@@ -112,69 +301,163 @@ pub mod solana_staking {
         require!(!staking.finished, StakingError::StakingFinished);
         require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
         require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
-        require!(current_time - staker_info.stake_time >= staking.round_time, StakingError::CantUnstakeInThisVeryRound);
-
-        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        let is_early_exit = staker_info.check_unstake(staking, current_time)?;
 
-        let period = current_time - staker_info.last_update_timestamp;        
-        staker_info.pending_bcdev_reward += period * staker_info.stake_size * staker_info.user_rpr;
+        staker_info.accrue_reward(staking, current_time)?;
+        staking.total_staked -= staker_info.stake_size;
 
         let staking_bump = staking.bump.to_le_bytes();
         let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
         let signer_seeds = [&seeds[..]];
 
-        let mut amount_to_give_to_user = staker_info.stake_size;
-
-        for (i, principal_accounts) in ctx.remaining_accounts.chunks_exact(3).enumerate() {
-            let pricipal_fctr_account = &mut Account::<TokenAccount>::try_from(&principal_accounts[0])?;
-            let pricipal_bcdev_account = &mut Account::<TokenAccount>::try_from(&principal_accounts[1])?;
-            let pricipal_info = &mut Account::<StakerInfo>::try_from(&principal_accounts[2])?;
-
-            require!(pricipal_fctr_account.mint.key() == staking.fctr_mint, StakingError::InvalidMint);
-            require!(pricipal_bcdev_account.mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
-            require!(pricipal_fctr_account.owner == staker_info.principals[i].principal, StakingError::InvalidTokenAccountOwner);
-            require!(pricipal_bcdev_account.owner == staker_info.principals[i].principal, StakingError::InvalidTokenAccountOwner);
-            require!(pricipal_info.staker == staker_info.principals[i].principal, StakingError::InvalidTokenAccountOwner);
+        let mut released_fctr = 0;
+        let mut commission_to_give_to_user = 0;
 
+        // Unstaking releases every principal still left after settle_principals: [fctr account, bcdev account, staker info, entrust record, wallet]
+        // per principal, optionally followed by the staker's history account
+        let principal_accounts_len = staker_info.principals_count as usize * PRINCIPAL_ACCOUNTS_NUM;
+        require!(
+            ctx.remaining_accounts.len() == principal_accounts_len || ctx.remaining_accounts.len() == principal_accounts_len + 1,
+            StakingError::InvalidRemainingAccounts
+        );
+        let (principals_accounts, history) = ctx.remaining_accounts.split_at(principal_accounts_len);
+        for principal_accounts in principals_accounts.chunks_exact(PRINCIPAL_ACCOUNTS_NUM) {
+            let PrincipalAccounts {
+                fctr_account: pricipal_fctr_account, bcdev_account: pricipal_bcdev_account, info: pricipal_info, entrust_record, wallet: principal_wallet
+            } = &mut PrincipalAccounts::load(staking, staker_info.staker, principal_accounts)?;
+
+            let (principal_reward, commission) = staker_info.release_on_unstake(pricipal_info, staking, entrust_record, current_time)?;
+            released_fctr += entrust_record.amount;
+            commission_to_give_to_user += commission;
+
+            give_fctr(
+                staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(),
+                ctx.accounts.fctr_vault.to_account_info(), pricipal_fctr_account.to_account_info(), entrust_record.amount
+            )?;
             let cpi_ctx = CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(), 
-                MintTo { mint: ctx.accounts.fctr_mint.to_account_info(), to: ctx.accounts.staker_fctr_account.to_account_info(), authority: staking.to_account_info() }, 
+                MintTo { mint: ctx.accounts.bcdev_mint.to_account_info(), to: pricipal_bcdev_account.to_account_info(), authority: staking.to_account_info() }, 
                 &signer_seeds
             );
-            token::mint_to(cpi_ctx, staker_info.principals[i].amount)?;
-            pricipal_info.ftcr_amount += staker_info.principals[i].amount;
-            pricipal_info.ftcr_amount += staker_info.principals[i].amount;
-            amount_to_give_to_user -= staker_info.principals[i].amount;
+            token::mint_to(cpi_ctx, principal_reward)?;
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(), 
-                MintTo { mint: ctx.accounts.fctr_mint.to_account_info(), to: ctx.accounts.staker_fctr_account.to_account_info(), authority: staking.to_account_info() }, 
-                &signer_seeds
-            );
-            token::mint_to(cpi_ctx, staker_info.pending_bcdev_reward * staker_info.principals[i].amount / staker_info.stake_size)?;
+            emit!(RewardSplit {
+                confidant: staker_info.staker,
+                principal: entrust_record.principal,
+                principal_reward,
+                confidant_commission: commission
+            });
+
+            pricipal_info.exit(ctx.program_id)?;
+            entrust_record.close(principal_wallet.clone())?;
         }
 
+        let (amount_to_give_to_user, reward_to_give_to_user) = staker_info.finish_unstake(staking, released_fctr, commission_to_give_to_user, is_early_exit, current_time);
+
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(), 
             MintTo { mint: ctx.accounts.bcdev_mint.to_account_info(), to: ctx.accounts.staker_bcdev_account.to_account_info(), authority: staking.to_account_info() }, 
             &signer_seeds
         );
-
-        let reward_to_give_to_user = (staker_info.pending_bcdev_reward as u128 * amount_to_give_to_user as u128 / staker_info.stake_size as u128) as u64;
         token::mint_to(cpi_ctx, reward_to_give_to_user)?;
 
-        staker_info.bcdev_amount += reward_to_give_to_user;
+        give_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(),
+            ctx.accounts.fctr_vault.to_account_info(), ctx.accounts.staker_fctr_account.to_account_info(), amount_to_give_to_user
+        )?;
+
+        record_history(history.first(), staker_info.staker, HistoryKind::Unstake, amount_to_give_to_user, current_time, ctx.program_id)?;
+
+        Ok(())
+    }
+
+    pub fn stake_liquid(ctx: Context<StakeLiquid>, amount: u64) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        let staker_info = &mut ctx.accounts.staker_info;
+
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(staking.sfctr_mint != Pubkey::default(), StakingError::LiquidStakingDisabled);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.sfctr_mint.key() == staking.sfctr_mint, StakingError::InvalidMint);
+        require!(amount > 0, StakingError::TooFewAmount);
+        require!(ctx.accounts.staker_fctr_account.amount >= amount && staker_info.spendable_fctr() >= amount, StakingError::NotEnoughTokens);
+
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        staking.accrue_liquid_reward(current_time)?;
+        let shares = math::shares_for_deposit(amount, ctx.accounts.sfctr_mint.supply, staking.liquid_value());
+
+        take_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            ctx.accounts.staker_fctr_account.to_account_info(), ctx.accounts.staker.to_account_info(), amount
+        )?;
+
+        let staking_bump = staking.bump.to_le_bytes();
+        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
 
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(), 
-            MintTo { mint: ctx.accounts.fctr_mint.to_account_info(), to: ctx.accounts.staker_fctr_account.to_account_info(), authority: staking.to_account_info() }, 
+            MintTo { mint: ctx.accounts.sfctr_mint.to_account_info(), to: ctx.accounts.staker_sfctr_account.to_account_info(), authority: staking.to_account_info() }, 
             &signer_seeds
         );
-        token::mint_to(cpi_ctx, amount_to_give_to_user)?;
+        token::mint_to(cpi_ctx, shares)?;
 
-        staker_info.ftcr_amount = amount_to_give_to_user;
-        staker_info.pending_bcdev_reward = 0;
-        staker_info.stake_size = 0;
+        staking.liquid_fctr += amount;
+        staker_info.ftcr_amount -= amount;
+
+        Ok(())
+    }
+
+    pub fn redeem_sfctr(ctx: Context<RedeemSfctr>, shares: u64) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.sfctr_mint.key() == staking.sfctr_mint, StakingError::InvalidMint);
+        require!(shares > 0 && ctx.accounts.holder_sfctr_account.amount >= shares, StakingError::NotEnoughTokens);
+
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        staking.accrue_liquid_reward(current_time)?;
+
+        let total_shares = ctx.accounts.sfctr_mint.supply;
+        let fctr_to_give = math::share_of(staking.liquid_fctr, shares, total_shares);
+        let bcdev_to_give = math::share_of(staking.liquid_pending_bcdev, shares, total_shares);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(), 
+            Burn { mint: ctx.accounts.sfctr_mint.to_account_info(), from: ctx.accounts.holder_sfctr_account.to_account_info(), authority: ctx.accounts.holder.to_account_info() }
+        );
+        token::burn(cpi_ctx, shares)?;
+
+        give_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(),
+            ctx.accounts.fctr_vault.to_account_info(), ctx.accounts.holder_fctr_account.to_account_info(), fctr_to_give
+        )?;
+
+        let staking_bump = staking.bump.to_le_bytes();
+        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(), 
+            MintTo { mint: ctx.accounts.bcdev_mint.to_account_info(), to: ctx.accounts.holder_bcdev_account.to_account_info(), authority: staking.to_account_info() }, 
+            &signer_seeds
+        );
+        token::mint_to(cpi_ctx, bcdev_to_give)?;
+        staking.total_bcdev_minted = staking.total_bcdev_minted.saturating_add(bcdev_to_give);
+
+        staking.liquid_fctr -= fctr_to_give;
+        staking.liquid_pending_bcdev -= bcdev_to_give;
+
+        // sFCTR is transferable, so the holder may be unregistered; a registered one passes its staker info as the only remaining account
+        if let Some(holder_info) = ctx.remaining_accounts.first() {
+            let mut holder_info = Account::<StakerInfo>::try_from(holder_info)?;
+            require_keys_eq!(holder_info.staker, ctx.accounts.holder.key(), StakingError::InvalidHolderInfoAccount);
+            holder_info.ftcr_amount += fctr_to_give;
+            holder_info.bcdev_amount += bcdev_to_give;
+            holder_info.exit(ctx.program_id)?;
+        }
 
         Ok(())
     }
@@ -183,21 +466,16 @@ pub mod solana_staking {
         let staking = &mut ctx.accounts.staking;
         let staker_info = &mut ctx.accounts.staker_info;
 
-        require!(!staking.finished, StakingError::StakingFinished);
-        require!(amount >= 10 * ONE_FCTR, StakingError::TooFewAmount);
         require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
-        require!(!staker_info.is_in_trust_program, StakingError::CantBuyInTrustProgram);
+        staker_info.check_buy_fctr(staking, amount)?;
 
         let current_time = Clock::get().unwrap().unix_timestamp as u64;
-        let period = current_time - staker_info.last_update_timestamp;
-        staker_info.pending_bcdev_reward += period * staker_info.stake_size * staker_info.user_rpr;
-        staker_info.last_update_timestamp = current_time;
+        staker_info.accrue_reward(staking, current_time)?;
 
         staker_info.bought_fctr += amount;
         staker_info.ftcr_amount += amount;
 
-        //fctr_amount / ONE_FCTR = 101 * sol_amount / LAMPORTS_PER_SOL; => 
-        let sol_to_take = amount / (ONE_FCTR / LAMPORTS_PER_SOL) / 109; 
+        let sol_to_take = math::fctr_buy_price(amount);
 
         let transfer_instruction = system_instruction::transfer(&ctx.accounts.user.key(), &staking.key(), sol_to_take);
 
@@ -219,6 +497,9 @@ pub mod solana_staking {
         token::mint_to(cpi_ctx, amount)?;
 
         staking.total_fctr_bought_by_users += amount;
+        staking.total_sol_in += sol_to_take;
+        staker_info.lifetime_sol_paid += sol_to_take;
+        record_history(ctx.remaining_accounts.last(), staker_info.staker, HistoryKind::BuyFctr, amount, current_time, ctx.program_id)?;
 
         Ok(())
     }
@@ -227,12 +508,10 @@ pub mod solana_staking {
         let staking = &mut ctx.accounts.staking;
         let staker_info = &mut ctx.accounts.staker_info;
 
-        require!(!staking.finished, StakingError::StakingFinished);
         require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
-        require!(staker_info.ftcr_amount >= amount && ctx.accounts.user_fctr_account.amount >= amount, StakingError::NotEnoughTokens);
+        staker_info.check_sell_fctr(staking, amount, ctx.accounts.user_fctr_account.amount)?;
 
-        //fctr_amount / ONE_FCTR = 101 * sol_amount / LAMPORTS_PER_SOL; => 
-        let sol_to_give = amount / (ONE_FCTR / LAMPORTS_PER_SOL) / 101; 
+        let sol_to_give = math::fctr_sell_price(amount);
 
         require!(**staking.to_account_info().lamports.borrow() >= sol_to_give, StakingError::NotEnoughFunds);
 
@@ -240,6 +519,7 @@ pub mod solana_staking {
 
         **staking.to_account_info().try_borrow_mut_lamports()? -= sol_to_give;
         **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += sol_to_give;
+        staking.total_sol_out += sol_to_give;
 
         let staking_bump = staking.bump.to_le_bytes();
         let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
@@ -255,6 +535,10 @@ pub mod solana_staking {
 
         staking.total_fctr_sold_by_users += amount;
         staker_info.ftcr_amount -= amount;
+        staker_info.lifetime_sol_received += sol_to_give;
+
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        record_history(ctx.remaining_accounts.last(), staker_info.staker, HistoryKind::SellFctr, amount, current_time, ctx.program_id)?;
 
         Ok(())
     }
@@ -263,14 +547,14 @@ pub mod solana_staking {
         let staking = &mut ctx.accounts.staking;
         let staker_info = &mut ctx.accounts.staker_info;
 
-        require!(!staking.finished, StakingError::StakingFinished);
         require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
-        require!(staker_info.bcdev_amount >= amount && ctx.accounts.user_bcdev_account.amount >= amount, StakingError::NotEnoughTokens);
+        staker_info.check_sell_bcdev(staking, amount, ctx.accounts.user_bcdev_account.amount)?;
 
-        let sol_to_give = amount / (ONE_BCDEV / LAMPORTS_PER_SOL) / 11; 
+        let sol_to_give = math::bcdev_sell_price(amount);
 
         **staking.to_account_info().try_borrow_mut_lamports()? -= sol_to_give;
         **ctx.accounts.user.try_borrow_mut_lamports()? += sol_to_give;
+        staking.total_sol_out += sol_to_give;
 
         let staking_bump = staking.bump.to_le_bytes();
         let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
@@ -286,85 +570,193 @@ pub mod solana_staking {
 
         staking.total_bcdev_sold_by_users += amount;
         staker_info.bcdev_amount -= amount;
+        staker_info.lifetime_sol_received += sol_to_give;
+
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        record_history(ctx.remaining_accounts.last(), staker_info.staker, HistoryKind::SellBcdev, amount, current_time, ctx.program_id)?;
 
         Ok(())
     }
 
-    pub fn entrust(ctx: Context<Entrust>, confidant: Pubkey) -> Result<()> {
+    // Views for clients, the result is passed back through the return data when simulated
+    pub fn quote_buy_fctr(_ctx: Context<Quote>, amount: u64) -> Result<u64> {
+        Ok(math::fctr_buy_price(amount))
+    }
+
+    pub fn quote_sell_fctr(_ctx: Context<Quote>, amount: u64) -> Result<u64> {
+        Ok(math::fctr_sell_price(amount))
+    }
+
+    pub fn quote_sell_bcdev(_ctx: Context<Quote>, amount: u64) -> Result<u64> {
+        Ok(math::bcdev_sell_price(amount))
+    }
+
+    pub fn pending_rewards(ctx: Context<PendingRewards>, _staker: Pubkey) -> Result<u64> {
+        let mut staker_info = ctx.accounts.staker_info.clone();
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+
+        staker_info.accrue_reward(&ctx.accounts.staking, current_time)?;
+        Ok(staker_info.pending_bcdev_reward)
+    }
+
+    pub fn sync_balances(ctx: Context<SyncBalances>, _staker: Pubkey) -> Result<()> {
+        let staker_info = &mut ctx.accounts.staker_info;
+        let staking = &ctx.accounts.staking;
+        require!(ctx.accounts.staker_fctr_account.mint == staking.fctr_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.staker_bcdev_account.mint == staking.bcdev_mint, StakingError::InvalidMint);
+
+        let fctr_held = ctx.accounts.staker_fctr_account.amount;
+        let bcdev_held = ctx.accounts.staker_bcdev_account.amount;
+
+        let fctr_recorded = staker_info.ftcr_amount;
+        let bcdev_recorded = staker_info.bcdev_amount;
+        if staker_info.reconcile_balances(fctr_held, bcdev_held) {
+            emit!(BalanceDrift {
+                staker: staker_info.staker,
+                fctr_recorded,
+                fctr_held,
+                bcdev_recorded,
+                bcdev_held
+            });
+        }
+        Ok(())
+    }
+
+    pub fn publish_offer(ctx: Context<PublishOffer>, accepting: bool, max_principals: u64, commission_bps: u64, min_amount: u64) -> Result<()> {
+        let confidant_offer = &mut ctx.accounts.confidant_offer;
+        require!(commission_bps <= BPS_DENOMINATOR, StakingError::InvalidOffer);
+
+        confidant_offer.confidant = ctx.accounts.confidant.key();
+        confidant_offer.accepting = accepting;
+        confidant_offer.max_principals = max_principals;
+        confidant_offer.commission_bps = commission_bps;
+        confidant_offer.min_amount = min_amount;
+        confidant_offer.bump = *ctx.bumps.get("confidant_offer").unwrap();
+        Ok(())
+    }
+
+    pub fn update_offer(ctx: Context<UpdateOffer>, accepting: bool, max_principals: u64, commission_bps: u64, min_amount: u64) -> Result<()> {
+        let confidant_offer = &mut ctx.accounts.confidant_offer;
+        require!(commission_bps <= BPS_DENOMINATOR, StakingError::InvalidOffer);
+
+        confidant_offer.accepting = accepting;
+        confidant_offer.max_principals = max_principals;
+        confidant_offer.commission_bps = commission_bps;
+        confidant_offer.min_amount = min_amount;
+        Ok(())
+    }
+
+    pub fn entrust(ctx: Context<Entrust>, confidant: Pubkey, amount: u64, term_rounds: u64) -> Result<()> {
         let principal_fctr_account = &mut ctx.accounts.principal_fctr_account;
 
         let principal_info = &mut ctx.accounts.principal_info;
         let confidant_info = &mut ctx.accounts.confidant_info;
-        let staking = &ctx.accounts.staking;
+        let staking = &mut ctx.accounts.staking;
 
-        let amount = principal_fctr_account.amount / 2;
+        let confidant_offer = &ctx.accounts.confidant_offer;
+        principal_info.check_entrust(confidant_info, confidant_offer, staking, amount, term_rounds, principal_fctr_account.amount)?;
 
-        require!(!staking.finished, StakingError::StakingFinished);
-        require!(confidant_info.principals.len() <= 4, StakingError::TooMuchPrincipals);
-        require!(principal_fctr_account.amount >= amount && principal_info.ftcr_amount >= amount, StakingError::InvalidTokenAccount);
-        require!(principal_fctr_account.amount >= principal_info.bought_fctr / 4 && principal_info.ftcr_amount >= principal_info.bought_fctr / 4, StakingError::InvalidAmountEntrusted);
-        require!(principal_info.ftcr_amount / 2 <= confidant_info.ftcr_amount && confidant_info.ftcr_amount <= principal_info.ftcr_amount * 2, StakingError::InvalidDepositDiff);
+        take_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            principal_fctr_account.to_account_info(), ctx.accounts.principal.to_account_info(), amount
+        )?;
 
-        principal_info.is_in_trust_program = true;
-        confidant_info.is_in_trust_program = true;
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        let entrust_record = &mut ctx.accounts.entrust_record;
+        entrust_record.confidant = confidant;
+        entrust_record.principal = principal_info.staker;
+        entrust_record.amount = amount;
+        entrust_record.commission_bps = confidant_offer.commission_bps;
+        entrust_record.rpr_boost = math::term_rpr_boost(term_rounds);
+        entrust_record.term_rounds = term_rounds;
+        entrust_record.term_end = current_time + term_rounds * staking.round_time;
+        entrust_record.bump = *ctx.bumps.get("entrust_record").unwrap();
+
+        confidant_info.take_entrustment(principal_info, staking, entrust_record, current_time)
+    }
 
-        let staking_bump = staking.bump.to_le_bytes();
-        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
-        let signer_seeds = [&seeds[..]];
+    pub fn demand_back(ctx: Context<DemandBack>, _confidant: Pubkey) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
 
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(), 
-            Burn { mint: ctx.accounts.fctr_mint.to_account_info(), from: principal_fctr_account.to_account_info(), authority: ctx.accounts.principal.to_account_info() }, 
-            &signer_seeds
-        );
+        require!(ctx.accounts.entrust_record.amount > 0, StakingError::NothingToDemandBack);
+        require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
 
-        token::burn(cpi_ctx, amount)?;
+        // Only the principal is bound by the term, the confidant may still release them early
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        require!(current_time >= ctx.accounts.entrust_record.term_end, StakingError::EntrustTermNotOver);
 
-        if confidant_info.is_staked {
-            confidant_info.stake_size += amount;
-        } else {
-            confidant_info.ftcr_amount += amount;
+        return_entrustment(
+            staking, &mut ctx.accounts.confidant_info, &mut ctx.accounts.principal_info, &ctx.accounts.entrust_record,
+            ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            ctx.accounts.principal_fctr_account.to_account_info(), ctx.accounts.bcdev_mint.to_account_info(), ctx.accounts.principal_bcdev_account.to_account_info()
+        )
+    }
+
+    pub fn release_principal(ctx: Context<ReleasePrincipal>, _principal: Pubkey) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+
+        require!(ctx.accounts.entrust_record.amount > 0, StakingError::NothingToDemandBack);
+        require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
+
+        return_entrustment(
+            staking, &mut ctx.accounts.confidant_info, &mut ctx.accounts.principal_info, &ctx.accounts.entrust_record,
+            ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+            ctx.accounts.principal_fctr_account.to_account_info(), ctx.accounts.bcdev_mint.to_account_info(), ctx.accounts.principal_bcdev_account.to_account_info()
+        )
+    }
+
+    // Releases principals in batches of remaining accounts laid out as in unstake, for confidants with more of them than fit into one transaction
+    pub fn settle_principals<'info>(ctx: Context<'_, '_, '_, 'info, SettlePrincipals<'info>>) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        let confidant_info = &mut ctx.accounts.confidant_info;
+
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(ctx.accounts.bcdev_mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
+        let principals_accounts = ctx.remaining_accounts.chunks_exact(PRINCIPAL_ACCOUNTS_NUM);
+        require!(!ctx.remaining_accounts.is_empty() && principals_accounts.remainder().is_empty(), StakingError::InvalidRemainingAccounts);
+
+        for principal_accounts in principals_accounts {
+            let mut principal = PrincipalAccounts::load(staking, confidant_info.staker, principal_accounts)?;
+            return_entrustment(
+                staking, confidant_info, &mut principal.info, &principal.entrust_record,
+                ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(), ctx.accounts.fctr_vault.to_account_info(),
+                principal.fctr_account.to_account_info(), ctx.accounts.bcdev_mint.to_account_info(), principal.bcdev_account.to_account_info()
+            )?;
+            principal.info.exit(ctx.program_id)?;
+            principal.entrust_record.close(principal.wallet)?;
         }
-        confidant_info.principals.push(state::EntrustInfo { principal: principal_info.staker, amount });
-        principal_info.user_rpr += 2;
-        principal_info.ftcr_amount -= amount;
-        confidant_info.ftcr_amount += amount;
 
         Ok(())
     }
 
-    pub fn demand_back(ctx: Context<DemandBack>, confidant: Pubkey) -> Result<()> {
+    // Compensates a principal out of the confidant's own stake, the entrusted funds stay untouched
+    pub fn slash_confidant(ctx: Context<SlashConfidant>, _confidant: Pubkey, _principal: Pubkey, slash_bps: u64) -> Result<()> {
+        let confidant_info = &mut ctx.accounts.confidant_info;
         let principal_info = &mut ctx.accounts.principal_info;
-        let confididant_info = &mut ctx.accounts.confidant_info;
         let staking = &mut ctx.accounts.staking;
+        let authority = ctx.accounts.authority.key();
 
-        let entrust_info = confididant_info.principals.iter_mut().find(|x|x.principal == principal_info.staker).ok_or(StakingError::NoSuchPrincipal)?;
-        let amount_to_take = entrust_info.amount;
-        
-        require!(amount_to_take > 0, StakingError::NothingToDemandBack);
-
-        entrust_info.amount = 0;
-        confididant_info.ftcr_amount -= amount_to_take;
-        principal_info.ftcr_amount += amount_to_take;
+        require!(authority == staking.owner || authority == staking.arbiter, StakingError::NotTheArbiter);
+        require!(ctx.accounts.fctr_mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(slash_bps > 0 && slash_bps <= BPS_DENOMINATOR, StakingError::InvalidSlash);
+        require!(confidant_info.is_staked, StakingError::InvalidSlash);
 
-        let staking_bump = staking.bump.to_le_bytes();
-        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
-        let signer_seeds = [&seeds[..]];
+        let current_time = Clock::get().unwrap().unix_timestamp as u64;
+        confidant_info.accrue_reward(staking, current_time)?;
 
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(), 
-            MintTo { mint: ctx.accounts.fctr_mint.to_account_info(), to: ctx.accounts.principal_fctr_account.to_account_info(), authority: ctx.accounts.fctr_mint.to_account_info() }, 
-            &signer_seeds
-        );
-        token::mint_to(cpi_ctx, amount_to_take)?;
+        let slashed = math::bps_share(confidant_info.own_stake(), slash_bps);
+        require!(slashed > 0, StakingError::InvalidSlash);
 
-        if confididant_info.is_staked {
-            confididant_info.stake_size -= amount_to_take;
-        } else {
-            confididant_info.ftcr_amount += amount_to_take;
-        }
+        confidant_info.stake_size -= slashed;
+        confidant_info.confidant_disputes += 1;
+        confidant_info.sync_bonus_debt(staking);
+        staking.total_staked -= slashed;
+        principal_info.ftcr_amount += slashed;
 
-        return Ok(())
+        give_fctr(
+            staking, ctx.accounts.token_program.to_account_info(), ctx.accounts.fctr_mint.to_account_info(),
+            ctx.accounts.fctr_vault.to_account_info(), ctx.accounts.principal_fctr_account.to_account_info(), slashed
+        )
     }
 
     pub fn stop(ctx: Context<Stop>) -> Result<()> {
@@ -373,6 +765,6 @@ pub mod solana_staking {
 
         staking.finished = true;
         staking.finish_time = current_time;
-        return Ok(());
+        Ok(())
     }
 }
\ No newline at end of file
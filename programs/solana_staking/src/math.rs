@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+
+use crate::error::StakingError;
+
+pub const ONE_FCTR: u64 = 10_u64.pow(12);
+pub const ONE_BCDEV: u64 = 10_u64.pow(18);
+
+pub const MULTIPLIER_PRECISION: u64 = 100;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const BONUS_PRECISION: u128 = 1_000_000_000_000;
+
+pub const BASE_RPR_BOOST: u64 = 2;
+pub const MAX_ENTRUST_TERM_ROUNDS: u64 = 12;
+pub const ROUNDS_PER_EXTRA_BOOST: u64 = 3;
+
+pub fn accrued_reward(period: u64, stake_size: u64, user_rpr: u64, multiplier: u64) -> Result<u64> {
+    let reward = period as u128 * stake_size as u128 * user_rpr as u128 * multiplier as u128 / MULTIPLIER_PRECISION as u128;
+    u64::try_from(reward).map_err(|_| error!(StakingError::RewardOverflow))
+}
+
+//fctr_amount / ONE_FCTR = 109 * sol_amount / LAMPORTS_PER_SOL
+pub fn fctr_buy_price(fctr_amount: u64) -> u64 {
+    fctr_amount / (ONE_FCTR / LAMPORTS_PER_SOL) / 109
+}
+
+//fctr_amount / ONE_FCTR = 101 * sol_amount / LAMPORTS_PER_SOL
+pub fn fctr_sell_price(fctr_amount: u64) -> u64 {
+    fctr_amount / (ONE_FCTR / LAMPORTS_PER_SOL) / 101
+}
+
+//bcdev_amount / ONE_BCDEV = 11 * sol_amount / LAMPORTS_PER_SOL
+pub fn bcdev_sell_price(bcdev_amount: u64) -> u64 {
+    bcdev_amount / (ONE_BCDEV / LAMPORTS_PER_SOL) / 11
+}
+
+// Entrusting for longer earns the principal a bigger rpr boost: +1 for every 3 rounds of the term
+pub fn term_rpr_boost(term_rounds: u64) -> u64 {
+    BASE_RPR_BOOST + term_rounds / ROUNDS_PER_EXTRA_BOOST
+}
+
+pub fn bps_share(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+pub fn bonus_per_share(amount: u64, total_staked: u64) -> u128 {
+    amount as u128 * BONUS_PRECISION / total_staked as u128
+}
+
+pub fn bonus_share(stake_size: u64, bonus_per_share: u128) -> u128 {
+    stake_size as u128 * bonus_per_share / BONUS_PRECISION
+}
+
+// Values BCDEV in FCTR at the prices they are sold back for: 11 BCDEV and 101 FCTR per SOL
+pub fn bcdev_to_fctr_value(bcdev_amount: u64) -> u64 {
+    (bcdev_amount as u128 * 101 * ONE_FCTR as u128 / 11 / ONE_BCDEV as u128) as u64
+}
+
+pub fn shares_for_deposit(amount: u64, total_shares: u64, pool_value: u64) -> u64 {
+    if total_shares == 0 || pool_value == 0 {
+        return amount;
+    }
+    (amount as u128 * total_shares as u128 / pool_value as u128) as u64
+}
+
+pub fn share_of(pool_amount: u64, shares: u64, total_shares: u64) -> u64 {
+    if total_shares == 0 {
+        return 0;
+    }
+    (pool_amount as u128 * shares as u128 / total_shares as u128) as u64
+}
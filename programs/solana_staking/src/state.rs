@@ -1,4 +1,65 @@
+use std::cmp::{max, min};
+
 use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::{math::{self, MULTIPLIER_PRECISION}, error::StakingError};
+
+pub const LOCK_TIERS_NUM: usize = 4;
+// Bumped whenever fields are appended, older accounts are brought up to date by the migrate instructions
+pub const STAKING_VERSION: u8 = 1;
+pub const STAKER_INFO_VERSION: u8 = 1;
+// New fields are carved out of the reserved bytes so that the account size doesn't change
+pub const STAKING_RESERVED_BYTES: usize = 16;
+pub const STAKER_INFO_RESERVED_BYTES: usize = 24;
+pub const HISTORY_LEN: usize = 16;
+
+// Accounts passed per principal when the confidant unstakes
+pub const PRINCIPAL_ACCOUNTS_NUM: usize = 5;
+// Before versioning every entrustment raised the principal's rpr by this much regardless of the term
+pub const LEGACY_RPR_BOOST: u64 = 2;
+
+pub const DEFAULT_LOCK_TIERS: [LockTier; LOCK_TIERS_NUM] = [
+    LockTier { rounds: 1, multiplier: 100 },
+    LockTier { rounds: 3, multiplier: 125 },
+    LockTier { rounds: 6, multiplier: 150 },
+    LockTier { rounds: 12, multiplier: 200 },
+];
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LockTier {
+    pub rounds: u64,
+    pub multiplier: u64
+}
+
+pub const DEFAULT_ENTRUST_BOUNDS: EntrustBounds = EntrustBounds {
+    min_fraction_bps: 2_500,
+    max_fraction_bps: 5_000,
+    min_deposit_ratio_bps: 5_000,
+    max_deposit_ratio_bps: 20_000
+};
+
+// Fractions are of the principal's bought FCTR (min) and current FCTR (max),
+// deposit ratios bound the confidant's FCTR relative to the principal's
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct EntrustBounds {
+    pub min_fraction_bps: u64,
+    pub max_fraction_bps: u64,
+    pub min_deposit_ratio_bps: u64,
+    pub max_deposit_ratio_bps: u64
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PenaltySource {
+    Stake,
+    Reward
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PenaltyDestination {
+    RewardPool,
+    Treasury
+}
 
 #[account]
 pub struct Staking {
@@ -13,20 +74,115 @@ pub struct Staking {
     pub fctr_mint: Pubkey,
     pub bcdev_mint: Pubkey,
     pub proof_signer: Pubkey,
-    pub bump: u8
+    pub bump: u8,
+    pub lock_tiers: [LockTier; 4],
+    pub early_exit_enabled: bool,
+    pub early_exit_penalty_bps: u64,
+    pub early_exit_penalty_source: PenaltySource,
+    pub early_exit_penalty_destination: PenaltyDestination,
+    pub total_staked: u64,
+    pub fctr_bonus_per_share: u128,
+    pub bcdev_bonus_per_share: u128,
+    pub treasury_fctr: u64,
+    pub treasury_bcdev: u64,
+    pub escrow_mode: bool,
+    pub fctr_vault: Pubkey,
+    pub sfctr_mint: Pubkey,
+    pub liquid_fctr: u64,
+    pub liquid_pending_bcdev: u64,
+    pub liquid_last_update: u64,
+    pub entrust_bounds: EntrustBounds,
+    pub arbiter: Pubkey,
+    pub version: u8,
+    pub active_stakers: u64,
+    pub registered_users: u64,
+    // Keeps counting past burns, so it saturates instead of failing the mint once it outgrows u64
+    pub total_bcdev_minted: u64,
+    pub total_entrusted_fctr: u64,
+    pub total_sol_in: u64,
+    pub total_sol_out: u64,
+    pub reserved: [u8; 16]
 }
 
 impl Staking {
-    pub const LEN: usize = 8*7 + 32*4 + 1;
+    pub const LEN: usize = 8*7 + 32*6 + 1 + (8 + 8) * LOCK_TIERS_NUM + 4 + 8*7 + 16*2 + 8*4 + 32 + 1 + 8*6 + STAKING_RESERVED_BYTES;
+
+    pub fn lock_end_for(&self, lock_tier: u8, current_time: u64) -> u64 {
+        current_time + self.lock_tiers[lock_tier as usize].rounds * self.round_time
+    }
+
+    // Forfeited stake is compounded into the remaining stakes, so it stays a part of total_staked
+    pub fn route_fctr_penalty(&mut self, amount: u64) {
+        if self.early_exit_penalty_destination == PenaltyDestination::RewardPool && self.total_staked > 0 {
+            self.fctr_bonus_per_share += math::bonus_per_share(amount, self.total_staked);
+            self.total_staked += amount;
+        } else {
+            self.treasury_fctr += amount;
+        }
+    }
+
+    // Liquid stakes earn at the base rate and the shortest lock tier as their receipts can change hands at any time
+    pub fn accrue_liquid_reward(&mut self, current_time: u64) -> Result<()> {
+        let period = current_time - self.liquid_last_update;
+        self.liquid_pending_bcdev += math::accrued_reward(period, self.liquid_fctr, 1, self.lock_tiers[0].multiplier)?;
+        self.liquid_last_update = current_time;
+        Ok(())
+    }
+
+    pub fn liquid_value(&self) -> u64 {
+        self.liquid_fctr + math::bcdev_to_fctr_value(self.liquid_pending_bcdev)
+    }
+
+    pub fn route_bcdev_penalty(&mut self, amount: u64) {
+        if self.early_exit_penalty_destination == PenaltyDestination::RewardPool && self.total_staked > 0 {
+            self.bcdev_bonus_per_share += math::bonus_per_share(amount, self.total_staked);
+        } else {
+            self.treasury_bcdev += amount;
+        }
+    }
+
+    // Earlier layouts are prefixes of the current one, so a grown account decodes as is and only needs the settings it lacked
+    pub fn migrate(&mut self) {
+        if self.version < 1 {
+            self.lock_tiers = DEFAULT_LOCK_TIERS;
+            self.entrust_bounds = DEFAULT_ENTRUST_BOUNDS;
+        }
+        self.version = STAKING_VERSION;
+    }
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
-pub struct EntrustInfo {
+#[account]
+pub struct EntrustRecord {
+    pub confidant: Pubkey,
     pub principal: Pubkey,
-    pub amount: u64
+    pub amount: u64,
+    pub commission_bps: u64,
+    pub rpr_boost: u64,
+    pub term_rounds: u64,
+    pub term_end: u64,
+    pub bump: u8
+}
+
+impl EntrustRecord {
+    pub const LEN: usize = 32*2 + 8*5 + 1;
+}
+
+#[account]
+pub struct ConfidantOffer {
+    pub confidant: Pubkey,
+    pub accepting: bool,
+    pub max_principals: u64,
+    pub commission_bps: u64,
+    pub min_amount: u64,
+    pub bump: u8
+}
+
+impl ConfidantOffer {
+    pub const LEN: usize = 32 + 1 + 8*3 + 1;
 }
 
 #[account()]
+#[derive(Default)]
 pub struct StakerInfo {
     pub staker: Pubkey,
     pub stake_size: u64,
@@ -40,11 +196,436 @@ pub struct StakerInfo {
     pub entrusted_tokens: bool,
     pub is_staked: bool,
     pub is_in_trust_program: bool,
-    pub principals: Vec<EntrustInfo>,
-    pub bump: u8
+    pub lock_tier: u8,
+    pub lock_end: u64,
+    pub fctr_bonus_debt: u128,
+    pub bcdev_bonus_debt: u128,
+    pub principals_count: u64,
+    pub entrusted_fctr: u64,
+    pub entrustments_count: u64,
+    pub confidant_total_entrusted: u64,
+    pub confidant_total_returned: u64,
+    pub confidant_total_term_rounds: u64,
+    pub confidant_principals_served: u64,
+    pub confidant_disputes: u64,
+    pub drift_detected: bool,
+    pub bump: u8,
+    pub version: u8,
+    pub lifetime_staked: u64,
+    // Saturates like Staking::total_bcdev_minted
+    pub lifetime_bcdev_earned: u64,
+    pub lifetime_sol_paid: u64,
+    pub lifetime_sol_received: u64,
+    pub rounds_participated: u64,
+    pub reserved: [u8; 24]
 }
 
 impl StakerInfo {
-    pub const LEN: usize = 32 + 8*8 + 1*5 + (4 + (32 + 8) * 4);
+    pub const LEN: usize = 32 + 8*17 + 6 + 16*2 + 1 + 8*5 + STAKER_INFO_RESERVED_BYTES;
+
+    // The tier multiplier only applies until the lock ends, past it the stake earns at the base rate
+    pub fn accrue_reward(&mut self, staking: &Staking, current_time: u64) -> Result<()> {
+        let locked_until = max(self.last_update_timestamp, min(current_time, self.lock_end));
+        let multiplier = staking.lock_tiers[self.lock_tier as usize].multiplier;
+        let locked_reward = math::accrued_reward(locked_until - self.last_update_timestamp, self.stake_size, self.user_rpr, multiplier)?;
+        let unlocked_reward = math::accrued_reward(current_time - locked_until, self.stake_size, self.user_rpr, MULTIPLIER_PRECISION)?;
+        self.pending_bcdev_reward = self.pending_bcdev_reward
+            .checked_add(locked_reward)
+            .and_then(|pending| pending.checked_add(unlocked_reward))
+            .ok_or(StakingError::RewardOverflow)?;
+
+        let fctr_bonus = math::bonus_share(self.stake_size, staking.fctr_bonus_per_share) - self.fctr_bonus_debt;
+        let bcdev_bonus = math::bonus_share(self.stake_size, staking.bcdev_bonus_per_share) - self.bcdev_bonus_debt;
+        self.stake_size += fctr_bonus as u64;
+        self.pending_bcdev_reward += bcdev_bonus as u64;
+        self.sync_bonus_debt(staking);
+
+        self.last_update_timestamp = current_time;
+        Ok(())
+    }
+
+    pub fn add_stake(&mut self, staking: &mut Staking, lock_tier: u8, amount: u64, current_time: u64) -> Result<()> {
+        require!((lock_tier as usize) < LOCK_TIERS_NUM, StakingError::InvalidLockTier);
+        require!(self.stake_size == 0 || lock_tier >= self.lock_tier, StakingError::LockTierDowngrade);
+        require!(self.is_staked || amount > 0, StakingError::NotEnoughTokens);
+
+        self.accrue_reward(staking, current_time)?;
+        if !self.is_staked {
+            staking.active_stakers += 1;
+            // Until it was set here stake_time stayed zero, so the first round gate in unstake and its early exit check never triggered
+            self.stake_time = current_time;
+            // FCTR entrusted while unstaked is already in custody, it goes into the stake along with the confidant's own
+            self.stake_size += self.entrusted_fctr;
+            staking.total_staked += self.entrusted_fctr;
+        }
+        self.is_staked = true;
+        self.lock_tier = lock_tier;
+        self.lock_end = max(self.lock_end, staking.lock_end_for(lock_tier, current_time));
+        self.stake_size += amount;
+        self.lifetime_staked += amount;
+        self.sync_bonus_debt(staking);
+        staking.total_staked += amount;
+        Ok(())
+    }
+
+    // Grows a running stake keeping its tier and lock end as they are
+    pub fn top_up_stake(&mut self, staking: &mut Staking, amount: u64, current_time: u64) -> Result<()> {
+        self.accrue_reward(staking, current_time)?;
+        self.stake_size += amount;
+        self.lifetime_staked += amount;
+        self.sync_bonus_debt(staking);
+        staking.total_staked += amount;
+        Ok(())
+    }
+
+    // Entrustments keep records pointing at the account, so it can't go away even once the program is finished
+    pub fn can_close(&self, staking: &Staking) -> bool {
+        let settled = !self.is_staked && self.stake_size == 0 && self.pending_bcdev_reward == 0;
+        !self.is_in_trust_program && (settled || staking.finished)
+    }
+
+    pub fn refresh_trust_status(&mut self) {
+        self.is_in_trust_program = self.principals_count > 0 || self.entrustments_count > 0;
+    }
+
+    // Reverts the principal's boost once the entrusted funds are back
+    pub fn leave_entrustment(&mut self, staking: &Staking, entrust_record: &EntrustRecord, current_time: u64) -> Result<()> {
+        self.accrue_reward(staking, current_time)?;
+        self.user_rpr -= entrust_record.rpr_boost;
+        self.entrustments_count -= 1;
+        self.refresh_trust_status();
+        Ok(())
+    }
+
+    pub fn add_principal(&mut self, entrust_record: &EntrustRecord) {
+        self.principals_count += 1;
+        self.entrusted_fctr += entrust_record.amount;
+        self.confidant_total_entrusted += entrust_record.amount;
+        self.confidant_total_term_rounds += entrust_record.term_rounds;
+        self.confidant_principals_served += 1;
+        self.refresh_trust_status();
+    }
+
+    pub fn drop_principal(&mut self, entrust_record: &EntrustRecord) {
+        self.principals_count -= 1;
+        self.entrusted_fctr -= entrust_record.amount;
+        self.confidant_total_returned += entrust_record.amount;
+        self.refresh_trust_status();
+    }
+
+    // An unstaked confidant is credited with the entrusted FCTR that sits in custody rather than in their account
+    pub fn custodied_fctr(&self) -> u64 {
+        if self.is_staked { 0 } else { self.entrusted_fctr }
+    }
+
+    // What the staker may sell, stake or entrust of the recorded FCTR, the custodied part is only there to be returned
+    pub fn spendable_fctr(&self) -> u64 {
+        self.ftcr_amount.saturating_sub(self.custodied_fctr())
+    }
+
+    // The checks shared by the trading handlers and the simulator, `*_held` is what the staker's token account holds
+    pub fn check_buy_fctr(&self, staking: &Staking, amount: u64) -> Result<()> {
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(amount >= 10 * math::ONE_FCTR, StakingError::TooFewAmount);
+        require!(!self.is_in_trust_program, StakingError::CantBuyInTrustProgram);
+        Ok(())
+    }
+
+    pub fn check_sell_fctr(&self, staking: &Staking, amount: u64, fctr_held: u64) -> Result<()> {
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(self.spendable_fctr() >= amount && fctr_held >= amount, StakingError::NotEnoughTokens);
+        Ok(())
+    }
+
+    pub fn check_sell_bcdev(&self, staking: &Staking, amount: u64, bcdev_held: u64) -> Result<()> {
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(self.bcdev_amount >= amount && bcdev_held >= amount, StakingError::NotEnoughTokens);
+        Ok(())
+    }
+
+    // Recorded balances may only shrink to what the staker actually holds, FCTR received outside of the program stays unsellable
+    pub fn reconcile_balances(&mut self, fctr_held: u64, bcdev_held: u64) -> bool {
+        let fctr_limit = fctr_held + self.custodied_fctr();
+
+        self.drift_detected = self.ftcr_amount > fctr_limit || self.bcdev_amount > bcdev_held;
+        self.ftcr_amount = min(self.ftcr_amount, fctr_limit);
+        self.bcdev_amount = min(self.bcdev_amount, bcdev_held);
+        self.drift_detected
+    }
+
+    pub fn average_term_rounds(&self) -> u64 {
+        if self.confidant_principals_served == 0 {
+            return 0;
+        }
+        self.confidant_total_term_rounds / self.confidant_principals_served
+    }
+
+    // Stake that belongs to the confidant, as opposed to the FCTR entrusted to them
+    pub fn own_stake(&self) -> u64 {
+        self.stake_size.saturating_sub(self.entrusted_fctr)
+    }
+
+    // Takes the principal's share out of the pending reward, the commission is left to the confidant
+    pub fn take_principal_reward(&mut self, entrust_record: &EntrustRecord) -> (u64, u64) {
+        if self.stake_size == 0 {
+            return (0, 0);
+        }
+        let reward = math::share_of(self.pending_bcdev_reward, entrust_record.amount, self.stake_size);
+        let commission = math::bps_share(reward, entrust_record.commission_bps);
+        self.pending_bcdev_reward -= reward - commission;
+        (reward - commission, commission)
+    }
+
+    // Checks an entrustment against the offer and the pool's bounds, called on the principal's info
+    pub fn check_entrust(&self, confidant_info: &StakerInfo, offer: &ConfidantOffer, staking: &Staking, amount: u64, term_rounds: u64, fctr_held: u64) -> Result<()> {
+        let bounds = staking.entrust_bounds;
+        require!(!staking.finished, StakingError::StakingFinished);
+        require!(confidant_info.staker != self.staker, StakingError::SelfEntrust);
+        require!(offer.accepting, StakingError::OfferNotAccepting);
+        require!(confidant_info.principals_count < offer.max_principals, StakingError::TooMuchPrincipals);
+        require!(amount >= offer.min_amount, StakingError::BelowOfferMinimum);
+        require!(term_rounds <= math::MAX_ENTRUST_TERM_ROUNDS, StakingError::InvalidEntrustTerm);
+        require!(fctr_held >= amount && self.spendable_fctr() >= amount, StakingError::InvalidTokenAccount);
+        require!(amount > 0 && amount >= math::bps_share(self.bought_fctr, bounds.min_fraction_bps), StakingError::InvalidAmountEntrusted);
+        require!(amount <= math::bps_share(self.ftcr_amount, bounds.max_fraction_bps), StakingError::InvalidAmountEntrusted);
+        require!(
+            math::bps_share(self.ftcr_amount, bounds.min_deposit_ratio_bps) <= confidant_info.ftcr_amount
+                && confidant_info.ftcr_amount <= math::bps_share(self.ftcr_amount, bounds.max_deposit_ratio_bps),
+            StakingError::InvalidDepositDiff
+        );
+        Ok(())
+    }
+
+    // Books an entrustment once the principal's FCTR is taken, called on the confidant's info
+    pub fn take_entrustment(&mut self, principal_info: &mut StakerInfo, staking: &mut Staking, entrust_record: &EntrustRecord, current_time: u64) -> Result<()> {
+        let amount = entrust_record.amount;
+        if self.is_staked {
+            self.accrue_reward(staking, current_time)?;
+            self.stake_size += amount;
+            self.sync_bonus_debt(staking);
+            staking.total_staked += amount;
+        } else {
+            self.ftcr_amount += amount;
+        }
+        self.add_principal(entrust_record);
+        staking.total_entrusted_fctr += amount;
+
+        principal_info.accrue_reward(staking, current_time)?;
+        principal_info.entrustments_count += 1;
+        principal_info.user_rpr += entrust_record.rpr_boost;
+        principal_info.refresh_trust_status();
+        principal_info.ftcr_amount -= amount;
+        Ok(())
+    }
+
+    // Books an entrustment handed back while the confidant stays, called on the confidant's info.
+    // A staked confidant also pays out the principal's share of the reward, returned along with the commission kept.
+    pub fn return_entrustment(&mut self, principal_info: &mut StakerInfo, staking: &mut Staking, entrust_record: &EntrustRecord, current_time: u64) -> Result<Option<(u64, u64)>> {
+        let amount = entrust_record.amount;
+
+        self.drop_principal(entrust_record);
+        staking.total_entrusted_fctr -= amount;
+        principal_info.leave_entrustment(staking, entrust_record, current_time)?;
+        principal_info.ftcr_amount += amount;
+
+        // An unstaked confidant only holds the custody credit, which spendable_fctr keeps from being spent
+        if !self.is_staked {
+            self.ftcr_amount -= amount;
+            return Ok(None);
+        }
+        self.accrue_reward(staking, current_time)?;
+        let (principal_reward, commission) = self.take_principal_reward(entrust_record);
+        staking.total_bcdev_minted = staking.total_bcdev_minted.saturating_add(principal_reward);
+        principal_info.bcdev_amount += principal_reward;
+        principal_info.lifetime_bcdev_earned = principal_info.lifetime_bcdev_earned.saturating_add(principal_reward);
+
+        self.stake_size -= amount;
+        self.sync_bonus_debt(staking);
+        staking.total_staked -= amount;
+        Ok(Some((principal_reward, commission)))
+    }
+
+    // Fails while the stake can't be left yet, otherwise tells whether leaving now is an early exit
+    pub fn check_unstake(&self, staking: &Staking, current_time: u64) -> Result<bool> {
+        if !staking.early_exit_enabled {
+            require!(current_time - self.stake_time >= staking.round_time, StakingError::CantUnstakeInThisVeryRound);
+            require!(current_time >= self.lock_end, StakingError::StakeIsLocked);
+        }
+        Ok(current_time - self.stake_time < staking.round_time || current_time < self.lock_end)
+    }
+
+    // Books a principal released by the confidant unstaking, called on the accrued confidant's info.
+    // The reward is split by the whole stake, so the confidant's pending reward is only settled in finish_unstake.
+    pub fn release_on_unstake(&mut self, principal_info: &mut StakerInfo, staking: &mut Staking, entrust_record: &EntrustRecord, current_time: u64) -> Result<(u64, u64)> {
+        principal_info.ftcr_amount += entrust_record.amount;
+
+        let reward = math::share_of(self.pending_bcdev_reward, entrust_record.amount, self.stake_size);
+        let commission = math::bps_share(reward, entrust_record.commission_bps);
+        staking.total_bcdev_minted = staking.total_bcdev_minted.saturating_add(reward - commission);
+        principal_info.bcdev_amount += reward - commission;
+        principal_info.lifetime_bcdev_earned = principal_info.lifetime_bcdev_earned.saturating_add(reward - commission);
+
+        principal_info.leave_entrustment(staking, entrust_record, current_time)?;
+        self.drop_principal(entrust_record);
+        staking.total_entrusted_fctr -= entrust_record.amount;
+        Ok((reward - commission, commission))
+    }
+
+    // Closes the position once every principal is released, `released_fctr` and `commission` summed over them.
+    // Returns the FCTR and BCDEV the staker is to be given.
+    pub fn finish_unstake(&mut self, staking: &mut Staking, released_fctr: u64, commission: u64, is_early_exit: bool, current_time: u64) -> (u64, u64) {
+        let mut fctr_to_give = self.stake_size - released_fctr;
+        let mut bcdev_to_give = math::share_of(self.pending_bcdev_reward, fctr_to_give, self.stake_size) + commission;
+
+        // Principals are not charged for the confidant leaving early
+        if is_early_exit {
+            match staking.early_exit_penalty_source {
+                PenaltySource::Stake => {
+                    let forfeited = math::bps_share(fctr_to_give, staking.early_exit_penalty_bps);
+                    fctr_to_give -= forfeited;
+                    staking.route_fctr_penalty(forfeited);
+                },
+                PenaltySource::Reward => {
+                    let forfeited = math::bps_share(bcdev_to_give, staking.early_exit_penalty_bps);
+                    bcdev_to_give -= forfeited;
+                    staking.route_bcdev_penalty(forfeited);
+                }
+            }
+        }
+
+        staking.total_bcdev_minted = staking.total_bcdev_minted.saturating_add(bcdev_to_give);
+        self.bcdev_amount += bcdev_to_give;
+        self.lifetime_bcdev_earned = self.lifetime_bcdev_earned.saturating_add(bcdev_to_give);
+        if self.is_staked {
+            // A position without a stake_time has no start to count its rounds from
+            if self.stake_time > 0 {
+                self.rounds_participated += (current_time - self.stake_time) / staking.round_time;
+            }
+            staking.active_stakers -= 1;
+        }
+
+        self.ftcr_amount = fctr_to_give;
+        self.pending_bcdev_reward = 0;
+        self.stake_size = 0;
+        self.is_staked = false;
+        self.sync_bonus_debt(staking);
+        (fctr_to_give, bcdev_to_give)
+    }
+
+    // Has to be called after every stake_size change so that early exit penalties distributed before it are not claimed twice
+    pub fn sync_bonus_debt(&mut self, staking: &Staking) {
+        self.fctr_bonus_debt = math::bonus_share(self.stake_size, staking.fctr_bonus_per_share);
+        self.bcdev_bonus_debt = math::bonus_share(self.stake_size, staking.bcdev_bonus_per_share);
+    }
+
+    // Pool counters start out zeroed on a migrated pool, every staker info adds itself to those that its version predates
+    pub fn backfill_counters(&self, staking: &mut Staking, from_version: u8) {
+        if from_version < 1 {
+            staking.total_staked += self.stake_size;
+            staking.registered_users += 1;
+            if self.is_staked {
+                staking.active_stakers += 1;
+            }
+            staking.total_entrusted_fctr += self.entrusted_fctr;
+        }
+    }
+
+    // Open entrustments are left out, the migration turns them into entrust records
+    pub fn from_legacy(legacy: &LegacyStakerInfo) -> Self {
+        Self {
+            staker: legacy.staker,
+            stake_size: legacy.stake_size,
+            stake_time: legacy.stake_time,
+            ftcr_amount: legacy.ftcr_amount,
+            bcdev_amount: legacy.bcdev_amount,
+            pending_bcdev_reward: legacy.pending_bcdev_reward,
+            last_update_timestamp: legacy.last_update_timestamp,
+            user_rpr: legacy.user_rpr,
+            bought_fctr: legacy.bought_fctr,
+            entrusted_tokens: legacy.entrusted_tokens,
+            // Unstaking used to leave the flag set
+            is_staked: legacy.is_staked && legacy.stake_size > 0,
+            bump: legacy.bump,
+            version: STAKER_INFO_VERSION,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(AnchorDeserialize, Clone, Copy)]
+pub struct LegacyEntrustInfo {
+    pub principal: Pubkey,
+    pub amount: u64
+}
+
+// StakerInfo as laid out before accounts were versioned, with the principals kept inline
+#[derive(AnchorDeserialize)]
+pub struct LegacyStakerInfo {
+    pub staker: Pubkey,
+    pub stake_size: u64,
+    pub stake_time: u64,
+    pub ftcr_amount: u64,
+    pub bcdev_amount: u64,
+    pub pending_bcdev_reward: u64,
+    pub last_update_timestamp: u64,
+    pub user_rpr: u64,
+    pub bought_fctr: u64,
+    pub entrusted_tokens: bool,
+    pub is_staked: bool,
+    pub is_in_trust_program: bool,
+    pub principals: Vec<LegacyEntrustInfo>,
+    pub bump: u8
 }
 
+impl LegacyStakerInfo {
+    pub const LEN: usize = 32 + 8*8 + 5 + (4 + (32 + 8) * 4);
+
+    pub fn try_from_account(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(*info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+        let data = info.try_borrow_data()?;
+        require!(data.len() >= 8 && data[..8] == StakerInfo::discriminator(), ErrorCode::AccountDiscriminatorMismatch);
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(ErrorCode::AccountDidNotDeserialize))
+    }
+
+    // Demanding back used to leave zeroed entries behind, and entrusting to oneself was never a real entrustment
+    pub fn open_principals(&self) -> Vec<LegacyEntrustInfo> {
+        self.principals.iter().filter(|p| p.amount > 0 && p.principal != self.staker).copied().collect()
+    }
+}
+
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryKind {
+    #[default]
+    None,
+    Stake,
+    Unstake,
+    BuyFctr,
+    SellFctr,
+    SellBcdev
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+// Ring buffer of the most recent actions, `head` points at the slot to be overwritten next
+#[account]
+pub struct StakerHistory {
+    pub staker: Pubkey,
+    pub head: u8,
+    pub entries: [HistoryEntry; 16],
+    pub bump: u8
+}
+
+impl StakerHistory {
+    pub const LEN: usize = 32 + 1 + (1 + 8*2) * HISTORY_LEN + 1;
+
+    pub fn push(&mut self, kind: HistoryKind, amount: u64, timestamp: u64) {
+        self.entries[self.head as usize] = HistoryEntry { kind, amount, timestamp };
+        self.head = ((self.head as usize + 1) % HISTORY_LEN) as u8;
+    }
+}
@@ -0,0 +1,165 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{system_instruction, program::{invoke, invoke_signed}};
+use anchor_spl::token::{self, MintTo, Burn, Transfer, TokenAccount};
+
+use crate::state::{Staking, StakerInfo, EntrustRecord, StakerHistory, HistoryKind, LegacyEntrustInfo, LEGACY_RPR_BOOST};
+use crate::error::StakingError;
+use crate::events::RewardSplit;
+
+// Staked FCTR is either burned and reminted on return or kept in the vault, depending on the pool mode
+pub fn take_fctr<'info>(staking: &Account<'info, Staking>, token_program: AccountInfo<'info>, fctr_mint: AccountInfo<'info>, fctr_vault: AccountInfo<'info>, from: AccountInfo<'info>, authority: AccountInfo<'info>, amount: u64) -> Result<()> {
+    if staking.escrow_mode {
+        require_keys_eq!(fctr_vault.key(), staking.fctr_vault);
+        let cpi_ctx = CpiContext::new(token_program, Transfer { from, to: fctr_vault, authority });
+        token::transfer(cpi_ctx, amount)
+    } else {
+        let cpi_ctx = CpiContext::new(token_program, Burn { mint: fctr_mint, from, authority });
+        token::burn(cpi_ctx, amount)
+    }
+}
+
+pub fn give_fctr<'info>(staking: &Account<'info, Staking>, token_program: AccountInfo<'info>, fctr_mint: AccountInfo<'info>, fctr_vault: AccountInfo<'info>, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+    let staking_bump = staking.bump.to_le_bytes();
+    let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
+    let signer_seeds = [&seeds[..]];
+
+    if staking.escrow_mode {
+        require_keys_eq!(fctr_vault.key(), staking.fctr_vault);
+        let cpi_ctx = CpiContext::new_with_signer(token_program, Transfer { from: fctr_vault, to, authority: staking.to_account_info() }, &signer_seeds);
+        token::transfer(cpi_ctx, amount)
+    } else {
+        let cpi_ctx = CpiContext::new_with_signer(token_program, MintTo { mint: fctr_mint, to, authority: staking.to_account_info() }, &signer_seeds);
+        token::mint_to(cpi_ctx, amount)
+    }
+}
+
+// Accounts a confidant passes per principal to release them: [fctr account, bcdev account, staker info, entrust record, wallet]
+pub struct PrincipalAccounts<'info> {
+    pub fctr_account: Account<'info, TokenAccount>,
+    pub bcdev_account: Account<'info, TokenAccount>,
+    pub info: Account<'info, StakerInfo>,
+    pub entrust_record: Account<'info, EntrustRecord>,
+    pub wallet: AccountInfo<'info>
+}
+
+impl<'info> PrincipalAccounts<'info> {
+    pub fn load(staking: &Staking, confidant: Pubkey, accounts: &[AccountInfo<'info>]) -> Result<Self> {
+        let fctr_account = Account::<TokenAccount>::try_from(&accounts[0])?;
+        let bcdev_account = Account::<TokenAccount>::try_from(&accounts[1])?;
+        let info = Account::<StakerInfo>::try_from(&accounts[2])?;
+        let entrust_record = Account::<EntrustRecord>::try_from(&accounts[3])?;
+        let wallet = accounts[4].clone();
+
+        require!(entrust_record.confidant == confidant, StakingError::NoSuchPrincipal);
+        require!(wallet.key() == entrust_record.principal, StakingError::InvalidPrincipalInfoAccount);
+        require!(fctr_account.mint.key() == staking.fctr_mint, StakingError::InvalidMint);
+        require!(bcdev_account.mint.key() == staking.bcdev_mint, StakingError::InvalidMint);
+        require!(fctr_account.owner == entrust_record.principal, StakingError::InvalidTokenAccountOwner);
+        require!(bcdev_account.owner == entrust_record.principal, StakingError::InvalidTokenAccountOwner);
+        require!(info.staker == entrust_record.principal, StakingError::InvalidPrincipalInfoAccount);
+
+        Ok(Self { fctr_account, bcdev_account, info, entrust_record, wallet })
+    }
+}
+
+// The history PDA is optional, clients that keep one pass it as the last remaining account
+pub fn record_history(history: Option<&AccountInfo>, staker: Pubkey, kind: HistoryKind, amount: u64, current_time: u64, program_id: &Pubkey) -> Result<()> {
+    if let Some(history_info) = history {
+        let mut history = Account::<StakerHistory>::try_from(history_info)?;
+        require_keys_eq!(history.staker, staker, StakingError::InvalidHistoryAccount);
+        history.push(kind, amount, current_time);
+        history.exit(program_id)?;
+    }
+    Ok(())
+}
+
+// Grows an account created with an older layout, the appended fields start zeroed
+pub fn grow_account<'info>(account: &AccountInfo<'info>, payer: AccountInfo<'info>, new_len: usize) -> Result<()> {
+    if account.data_len() >= new_len {
+        return Ok(());
+    }
+
+    let rent_due = Rent::get()?.minimum_balance(new_len).saturating_sub(account.lamports());
+    if rent_due > 0 {
+        invoke(&system_instruction::transfer(&payer.key(), &account.key(), rent_due), &[payer, account.clone()])?;
+    }
+    account.realloc(new_len, true)?;
+    Ok(())
+}
+
+// Turns an entrustment the pre-versioning layout kept inline into an entrust record, so it has to be the principal's account that is migrated first
+#[allow(clippy::too_many_arguments)]
+pub fn adopt_legacy_entrustment<'info>(
+    staking: &mut Staking, confidant_info: &mut StakerInfo, legacy: &LegacyEntrustInfo, entrust_record: &AccountInfo<'info>,
+    principal_info: &AccountInfo<'info>, payer: AccountInfo<'info>, program_id: &Pubkey
+) -> Result<()> {
+    let mut principal_info = Account::<StakerInfo>::try_from(principal_info)?;
+    require_keys_eq!(principal_info.staker, legacy.principal, StakingError::InvalidPrincipalInfoAccount);
+
+    let (record_address, bump) = Pubkey::find_program_address(&[b"entrust", confidant_info.staker.as_ref(), legacy.principal.as_ref()], program_id);
+    require_keys_eq!(entrust_record.key(), record_address, StakingError::InvalidRemainingAccounts);
+
+    // Funding, allocating and assigning separately rather than create_account, which fails on an address someone has sent lamports to
+    let space = 8 + EntrustRecord::LEN;
+    let seeds = &[b"entrust".as_ref(), confidant_info.staker.as_ref(), legacy.principal.as_ref(), &[bump]];
+    let rent_due = Rent::get()?.minimum_balance(space).saturating_sub(entrust_record.lamports());
+    if rent_due > 0 {
+        invoke(&system_instruction::transfer(&payer.key(), &record_address, rent_due), &[payer, entrust_record.clone()])?;
+    }
+    invoke_signed(&system_instruction::allocate(&record_address, space as u64), std::slice::from_ref(entrust_record), &[seeds])?;
+    invoke_signed(&system_instruction::assign(&record_address, program_id), std::slice::from_ref(entrust_record), &[seeds])?;
+
+    let record = EntrustRecord {
+        confidant: confidant_info.staker,
+        principal: legacy.principal,
+        amount: legacy.amount,
+        commission_bps: 0,
+        rpr_boost: LEGACY_RPR_BOOST,
+        term_rounds: 0,
+        term_end: 0,
+        bump
+    };
+    record.try_serialize(&mut &mut entrust_record.try_borrow_mut_data()?[..])?;
+
+    confidant_info.add_principal(&record);
+    staking.total_entrusted_fctr += record.amount;
+    principal_info.entrustments_count += 1;
+    principal_info.refresh_trust_status();
+    principal_info.exit(program_id)
+}
+
+// Unwinds an entrustment: the principal gets the FCTR back along with its share of the confidant's accrued reward
+#[allow(clippy::too_many_arguments)]
+pub fn return_entrustment<'info>(
+    staking: &mut Account<'info, Staking>, confidant_info: &mut Account<'info, StakerInfo>, principal_info: &mut Account<'info, StakerInfo>,
+    entrust_record: &Account<'info, EntrustRecord>, token_program: AccountInfo<'info>, fctr_mint: AccountInfo<'info>, fctr_vault: AccountInfo<'info>,
+    principal_fctr_account: AccountInfo<'info>, bcdev_mint: AccountInfo<'info>, principal_bcdev_account: AccountInfo<'info>
+) -> Result<()> {
+    let amount = entrust_record.amount;
+    let current_time = Clock::get().unwrap().unix_timestamp as u64;
+
+    let reward_split = confidant_info.return_entrustment(principal_info, staking, entrust_record, current_time)?;
+    give_fctr(staking, token_program.clone(), fctr_mint, fctr_vault, principal_fctr_account, amount)?;
+
+    if let Some((principal_reward, commission)) = reward_split {
+        let staking_bump = staking.bump.to_le_bytes();
+        let seeds = &[b"staking".as_ref(), staking_bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program,
+            MintTo { mint: bcdev_mint, to: principal_bcdev_account, authority: staking.to_account_info() },
+            &signer_seeds
+        );
+        token::mint_to(cpi_ctx, principal_reward)?;
+
+        emit!(RewardSplit {
+            confidant: confidant_info.staker,
+            principal: principal_info.staker,
+            principal_reward,
+            confidant_commission: commission
+        });
+    }
+
+    Ok(())
+}
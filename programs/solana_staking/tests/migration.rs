@@ -0,0 +1,180 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use solana_staking::state::{Staking, StakerInfo, LegacyStakerInfo, DEFAULT_LOCK_TIERS, DEFAULT_ENTRUST_BOUNDS, STAKING_VERSION, STAKER_INFO_VERSION};
+
+// Size the pre-versioning program allocated for the staking account
+const LEGACY_STAKING_LEN: usize = 8*7 + 32*4 + 1;
+
+fn put_u64(data: &mut Vec<u8>, value: u64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_key(data: &mut Vec<u8>, key: &Pubkey) {
+    data.extend_from_slice(key.as_ref());
+}
+
+// Lays the fields out by hand the way the pre-versioning program serialized them, zero padded to the size it allocated
+fn legacy_staking(owner: &Pubkey, fctr_mint: &Pubkey, bcdev_mint: &Pubkey, proof_signer: &Pubkey) -> Vec<u8> {
+    let mut data = Staking::discriminator().to_vec();
+    put_key(&mut data, owner);
+    put_u64(&mut data, 3600);
+    put_u64(&mut data, 7);
+    put_u64(&mut data, 1_000);
+    put_u64(&mut data, 200);
+    put_u64(&mut data, 30);
+    data.push(0);
+    put_u64(&mut data, 0);
+    put_key(&mut data, fctr_mint);
+    put_key(&mut data, bcdev_mint);
+    put_key(&mut data, proof_signer);
+    data.push(254);
+    data.resize(8 + LEGACY_STAKING_LEN, 0);
+    data
+}
+
+fn legacy_staker_info(staker: &Pubkey, is_staked: bool, stake_size: u64, principals: &[(Pubkey, u64)]) -> Vec<u8> {
+    let mut data = StakerInfo::discriminator().to_vec();
+    put_key(&mut data, staker);
+    put_u64(&mut data, stake_size);
+    put_u64(&mut data, 100);
+    put_u64(&mut data, 40);
+    put_u64(&mut data, 3);
+    put_u64(&mut data, 9);
+    put_u64(&mut data, 110);
+    put_u64(&mut data, 5);
+    put_u64(&mut data, 80);
+    data.push(0);
+    data.push(is_staked as u8);
+    data.push(!principals.is_empty() as u8);
+    data.extend_from_slice(&(principals.len() as u32).to_le_bytes());
+    for (principal, amount) in principals {
+        put_key(&mut data, principal);
+        put_u64(&mut data, *amount);
+    }
+    data.push(253);
+    data.resize(8 + LegacyStakerInfo::LEN, 0);
+    data
+}
+
+#[test]
+fn staking_keeps_its_fields_and_gets_the_default_settings() {
+    let (owner, fctr_mint, bcdev_mint, proof_signer) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = legacy_staking(&owner, &fctr_mint, &bcdev_mint, &proof_signer);
+    let legacy = data.clone();
+    // What the realloc leaves behind
+    data.resize(8 + Staking::LEN, 0);
+
+    let mut staking = Staking::try_deserialize(&mut &data[..]).unwrap();
+    assert_eq!(staking.version, 0);
+    staking.migrate();
+
+    assert_eq!(staking.owner, owner);
+    assert_eq!(staking.round_time, 3600);
+    assert_eq!(staking.rounds_num, 7);
+    assert_eq!(staking.total_fctr_bought_by_users, 1_000);
+    assert_eq!(staking.total_fctr_sold_by_users, 200);
+    assert_eq!(staking.total_bcdev_sold_by_users, 30);
+    assert!(!staking.finished);
+    assert_eq!(staking.fctr_mint, fctr_mint);
+    assert_eq!(staking.bcdev_mint, bcdev_mint);
+    assert_eq!(staking.proof_signer, proof_signer);
+    assert_eq!(staking.bump, 254);
+    for (tier, default) in staking.lock_tiers.iter().zip(DEFAULT_LOCK_TIERS.iter()) {
+        assert_eq!((tier.rounds, tier.multiplier), (default.rounds, default.multiplier));
+    }
+    assert_eq!(staking.entrust_bounds.min_fraction_bps, DEFAULT_ENTRUST_BOUNDS.min_fraction_bps);
+    assert_eq!(staking.entrust_bounds.max_fraction_bps, DEFAULT_ENTRUST_BOUNDS.max_fraction_bps);
+    assert_eq!(staking.entrust_bounds.min_deposit_ratio_bps, DEFAULT_ENTRUST_BOUNDS.min_deposit_ratio_bps);
+    assert_eq!(staking.entrust_bounds.max_deposit_ratio_bps, DEFAULT_ENTRUST_BOUNDS.max_deposit_ratio_bps);
+    assert_eq!(staking.version, STAKING_VERSION);
+
+    let mut migrated = Vec::new();
+    staking.try_serialize(&mut migrated).unwrap();
+    // Staking::LEN has been rounded up since the first version
+    assert!(migrated.len() <= 8 + Staking::LEN);
+    let legacy_fields_len = 8 + 32 + 8*5 + 1 + 8 + 32*3 + 1;
+    assert_eq!(migrated[..legacy_fields_len], legacy[..legacy_fields_len]);
+}
+
+#[test]
+fn staker_info_moves_past_the_inline_principals() {
+    let staker = Pubkey::new_unique();
+    let (open, demanded_back) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = legacy_staker_info(&staker, true, 500, &[(open, 60), (demanded_back, 0), (staker, 10)]);
+    assert_eq!(data.len(), 8 + LegacyStakerInfo::LEN);
+    let legacy_data = data.clone();
+
+    let mut lamports = 0;
+    let info = AccountInfo::new(&staker, false, true, &mut lamports, &mut data, &solana_staking::ID, false, 0);
+    let legacy = LegacyStakerInfo::try_from_account(&info).unwrap();
+    let staker_info = StakerInfo::from_legacy(&legacy);
+
+    assert_eq!(staker_info.staker, staker);
+    assert_eq!(staker_info.stake_size, 500);
+    assert_eq!(staker_info.stake_time, 100);
+    assert_eq!(staker_info.ftcr_amount, 40);
+    assert_eq!(staker_info.bcdev_amount, 3);
+    assert_eq!(staker_info.pending_bcdev_reward, 9);
+    assert_eq!(staker_info.last_update_timestamp, 110);
+    assert_eq!(staker_info.user_rpr, 5);
+    assert_eq!(staker_info.bought_fctr, 80);
+    assert!(staker_info.is_staked);
+    assert_eq!(staker_info.bump, 253);
+    assert_eq!(staker_info.version, STAKER_INFO_VERSION);
+    // Trust status and counters are restored as the open principals are turned into entrust records
+    assert!(!staker_info.is_in_trust_program);
+    assert_eq!(staker_info.principals_count, 0);
+
+    let open_principals = legacy.open_principals();
+    assert_eq!(open_principals.len(), 1);
+    assert_eq!((open_principals[0].principal, open_principals[0].amount), (open, 60));
+
+    let mut migrated = Vec::new();
+    staker_info.try_serialize(&mut migrated).unwrap();
+    assert_eq!(migrated.len(), 8 + StakerInfo::LEN);
+    let legacy_fields_len = 8 + 32 + 8*8 + 2;
+    assert_eq!(migrated[..legacy_fields_len], legacy_data[..legacy_fields_len]);
+    assert_eq!(StakerInfo::try_deserialize(&mut &migrated[..]).unwrap().bump, 253);
+}
+
+#[test]
+fn staker_info_left_staked_by_unstaking_is_not_staked() {
+    let staker = Pubkey::new_unique();
+    let mut data = legacy_staker_info(&staker, true, 0, &[]);
+
+    let mut lamports = 0;
+    let info = AccountInfo::new(&staker, false, true, &mut lamports, &mut data, &solana_staking::ID, false, 0);
+    let staker_info = StakerInfo::from_legacy(&LegacyStakerInfo::try_from_account(&info).unwrap());
+    assert!(!staker_info.is_staked);
+}
+
+#[test]
+fn staker_info_of_another_program_is_rejected() {
+    let staker = Pubkey::new_unique();
+    let mut data = legacy_staker_info(&staker, false, 0, &[]);
+
+    let mut lamports = 0;
+    let owner = Pubkey::new_unique();
+    let info = AccountInfo::new(&staker, false, true, &mut lamports, &mut data, &owner, false, 0);
+    assert!(LegacyStakerInfo::try_from_account(&info).is_err());
+}
+
+#[test]
+fn counters_are_backfilled_from_legacy_accounts() {
+    let mut data = legacy_staking(&Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique());
+    data.resize(8 + Staking::LEN, 0);
+    let mut staking = Staking::try_deserialize(&mut &data[..]).unwrap();
+    staking.migrate();
+
+    let staker = Pubkey::new_unique();
+    let mut data = legacy_staker_info(&staker, true, 500, &[]);
+    let mut lamports = 0;
+    let info = AccountInfo::new(&staker, false, true, &mut lamports, &mut data, &solana_staking::ID, false, 0);
+    StakerInfo::from_legacy(&LegacyStakerInfo::try_from_account(&info).unwrap()).backfill_counters(&mut staking, 0);
+
+    assert_eq!(staking.total_staked, 500);
+    assert_eq!(staking.registered_users, 1);
+    assert_eq!(staking.active_stakers, 1);
+    assert_eq!(staking.total_entrusted_fctr, 0);
+}
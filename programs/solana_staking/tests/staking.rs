@@ -0,0 +1,73 @@
+use anchor_lang::prelude::*;
+
+use solana_staking::error::StakingError;
+use solana_staking::state::{Staking, StakerInfo, DEFAULT_LOCK_TIERS};
+
+const ROUND_TIME: u64 = 3600;
+
+fn zeroed<T: AccountDeserialize>(len: usize) -> T {
+    T::try_deserialize_unchecked(&mut &vec![0; 8 + len][..]).unwrap()
+}
+
+fn new_staking() -> Staking {
+    let mut staking: Staking = zeroed(Staking::LEN);
+    staking.round_time = ROUND_TIME;
+    staking.lock_tiers = DEFAULT_LOCK_TIERS;
+    staking
+}
+
+fn new_staker_info() -> StakerInfo {
+    StakerInfo { staker: Pubkey::new_unique(), user_rpr: 1, ..StakerInfo::default() }
+}
+
+#[test]
+fn position_cant_be_opened_with_nothing() {
+    let mut staking = new_staking();
+    let mut info = new_staker_info();
+
+    let err = info.add_stake(&mut staking, 0, 0, ROUND_TIME).unwrap_err();
+    assert_eq!(err, StakingError::NotEnoughTokens.into());
+    assert!(!info.is_staked);
+    assert_eq!(staking.active_stakers, 0);
+
+    // A running position may still be extended without adding to it
+    info.add_stake(&mut staking, 0, 10, ROUND_TIME).unwrap();
+    info.add_stake(&mut staking, 1, 0, ROUND_TIME).unwrap();
+    assert_eq!(info.lock_tier, 1);
+    assert_eq!(info.stake_size, 10);
+}
+
+#[test]
+fn emptied_position_can_be_left() {
+    let mut staking = new_staking();
+    let mut info = new_staker_info();
+    info.add_stake(&mut staking, 0, 10, ROUND_TIME).unwrap();
+
+    // All of the stake is gone, e.g. slashed away
+    info.stake_size = 0;
+    staking.total_staked = 0;
+
+    let current_time = 3 * ROUND_TIME;
+    info.accrue_reward(&staking, current_time).unwrap();
+    let is_early_exit = info.check_unstake(&staking, current_time).unwrap();
+    assert_eq!(info.finish_unstake(&mut staking, 0, 0, is_early_exit, current_time), (0, 0));
+    assert!(!info.is_staked);
+    assert_eq!(staking.active_stakers, 0);
+}
+
+#[test]
+fn position_cant_be_left_in_the_round_it_was_opened() {
+    let mut staking = new_staking();
+    let mut info = new_staker_info();
+    info.add_stake(&mut staking, 0, 10, ROUND_TIME).unwrap();
+    assert_eq!(info.stake_time, ROUND_TIME);
+
+    let lock_end = info.lock_end;
+    let err = info.check_unstake(&staking, ROUND_TIME + 1).unwrap_err();
+    assert_eq!(err, StakingError::CantUnstakeInThisVeryRound.into());
+    assert!(!info.check_unstake(&staking, lock_end).unwrap());
+
+    // With early exit enabled it can, but as an early exit
+    staking.early_exit_enabled = true;
+    assert!(info.check_unstake(&staking, ROUND_TIME + 1).unwrap());
+}
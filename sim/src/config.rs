@@ -0,0 +1,85 @@
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_staking::math::BPS_DENOMINATOR;
+use solana_staking::state::{LockTier, EntrustBounds, DEFAULT_LOCK_TIERS, DEFAULT_ENTRUST_BOUNDS, LOCK_TIERS_NUM};
+
+pub struct Config {
+    pub users: usize,
+    pub rounds: u64,
+    pub round_time: u64,
+    pub seed: u64,
+    // Lamports the owner funds the reserve with before anyone buys
+    pub initial_reserve: u64,
+    // Share of the users that publish a confidant offer
+    pub confidants_bps: u64,
+    // Upper bound of a single purchase in whole FCTR, the lower one is the program's 10 FCTR
+    pub max_buy_fctr: u64,
+    pub lock_tiers: [LockTier; LOCK_TIERS_NUM],
+    pub entrust_bounds: EntrustBounds,
+    pub weights: ActionWeights
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            users: 1_000,
+            rounds: 100,
+            round_time: 1,
+            seed: 0,
+            initial_reserve: 100 * LAMPORTS_PER_SOL,
+            confidants_bps: 1_000,
+            max_buy_fctr: 1_000,
+            lock_tiers: DEFAULT_LOCK_TIERS,
+            entrust_bounds: DEFAULT_ENTRUST_BOUNDS,
+            weights: ActionWeights::default()
+        }
+    }
+}
+
+impl Config {
+    // Values the simulation can't run with, it would panic on them midway otherwise
+    pub fn validate(&self) -> Result<(), String> {
+        if self.round_time == 0 {
+            return Err("round time must be positive".to_string());
+        }
+        if self.confidants_bps > BPS_DENOMINATOR {
+            return Err(format!("confidants share can't exceed {BPS_DENOMINATOR} bps"));
+        }
+        if self.max_buy_fctr < 10 {
+            return Err("max buy can't be below the program's minimum of 10 FCTR".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Relative odds of what a user does in a round
+pub struct ActionWeights {
+    pub buy_fctr: u32,
+    pub stake: u32,
+    pub unstake: u32,
+    pub entrust: u32,
+    pub demand_back: u32,
+    pub sell_fctr: u32,
+    pub sell_bcdev: u32,
+    pub idle: u32
+}
+
+impl Default for ActionWeights {
+    fn default() -> Self {
+        ActionWeights {
+            buy_fctr: 20,
+            stake: 20,
+            unstake: 10,
+            entrust: 10,
+            demand_back: 5,
+            sell_fctr: 5,
+            sell_bcdev: 15,
+            idle: 15
+        }
+    }
+}
+
+impl ActionWeights {
+    pub fn total(&self) -> u32 {
+        self.buy_fctr + self.stake + self.unstake + self.entrust + self.demand_back + self.sell_fctr + self.sell_bcdev + self.idle
+    }
+}
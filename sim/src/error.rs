@@ -0,0 +1,10 @@
+use anchor_lang::prelude::*;
+
+// Failures the program doesn't check for explicitly, on-chain the transaction aborts instead
+#[error_code]
+pub enum SimError {
+    #[msg("The instruction would abort on an arithmetic error")]
+    ProgramAbort,
+    #[msg("The entrust record for this pair already exists")]
+    EntrustRecordExists
+}
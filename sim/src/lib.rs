@@ -0,0 +1,14 @@
+#![allow(clippy::result_large_err)]
+
+// Off-chain model of the staking program for evaluating parameter changes.
+// State updates go through the program's own Staking/StakerInfo methods and math, settlements included,
+// only the checks of the instruction handlers and the token balances their transfers change are repeated here.
+
+pub mod config;
+pub mod error;
+pub mod sim;
+pub mod report;
+
+pub use config::{Config, ActionWeights};
+pub use sim::{Sim, User, Entrustment, Instruction};
+pub use report::Snapshot;
@@ -0,0 +1,61 @@
+use std::env;
+use std::process;
+
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use staking_sim::{Config, Sim, Snapshot};
+
+const USAGE: &str = "usage: staking_sim [--users N] [--rounds N] [--round-time SECONDS] [--seed N] \
+    [--reserve SOL] [--confidants-bps BPS] [--max-buy FCTR] [--every ROUNDS]";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(1);
+}
+
+fn main() {
+    let mut config = Config::default();
+    let mut every = 10;
+
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value: u64 = args.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--users" => config.users = value as usize,
+            "--rounds" => config.rounds = value,
+            "--round-time" => config.round_time = value,
+            "--seed" => config.seed = value,
+            "--reserve" => config.initial_reserve = value * LAMPORTS_PER_SOL,
+            "--confidants-bps" => config.confidants_bps = value,
+            "--max-buy" => config.max_buy_fctr = value,
+            "--every" => every = value.max(1),
+            _ => usage()
+        }
+    }
+
+    if let Err(err) = config.validate() {
+        eprintln!("error: {err}");
+        usage();
+    }
+
+    let rounds = config.rounds;
+    let mut sim = Sim::new(config);
+    let mut previous = sim.snapshot();
+
+    println!("{}", Snapshot::header());
+    for _ in 0..rounds {
+        let snapshot = sim.run_round();
+        if snapshot.round.is_multiple_of(every) || snapshot.round == rounds {
+            println!("{}", snapshot.row(&previous));
+            previous = snapshot;
+        }
+    }
+
+    println!("\napplied:");
+    for (instruction, count) in &sim.applied {
+        println!("  {instruction:<12} {count}");
+    }
+    println!("rejected:");
+    for (error, count) in &sim.rejected {
+        println!("  {error:<28} {count}");
+    }
+}
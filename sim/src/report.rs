@@ -0,0 +1,58 @@
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_staking::math::{ONE_FCTR, ONE_BCDEV};
+
+// State of the economy at the end of a round, SOL figures are in lamports and token figures in base units
+pub struct Snapshot {
+    pub round: u64,
+    pub reserve: u64,
+    pub liabilities: u64,
+    pub total_staked: u64,
+    pub active_stakers: u64,
+    pub bcdev_minted: u64,
+    pub bcdev_supply: u64,
+    pub pending_bcdev: u128,
+    pub total_entrusted_fctr: u64,
+    pub entrustments: usize,
+    // Positions whose reward no longer fits into u64, they are left out of the liabilities
+    pub overflowed_positions: usize
+}
+
+impl Snapshot {
+    // Reserve over what users could redeem, below 100 the last ones to sell won't get paid
+    pub fn solvency_percent(&self) -> f64 {
+        if self.liabilities == 0 {
+            return f64::INFINITY;
+        }
+        self.reserve as f64 * 100.0 / self.liabilities as f64
+    }
+
+    pub fn header() -> String {
+        format!(
+            "{:>6} {:>12} {:>12} {:>9} {:>14} {:>8} {:>10} {:>10} {:>10} {:>14} {:>8} {:>10}",
+            "round", "reserve", "liabilities", "solvency", "staked FCTR", "stakers", "minted", "supply", "pending", "entrusted FCTR", "entrusts", "overflowed"
+        )
+    }
+
+    // BCDEV minted is counted since `previous`, so that rows can be printed at any interval
+    pub fn row(&self, previous: &Snapshot) -> String {
+        format!(
+            "{:>6} {:>12.3} {:>12.3} {:>8.1}% {:>14.3} {:>8} {:>10.6} {:>10.6} {:>10.6} {:>14.3} {:>8} {:>10}",
+            self.round,
+            amount(self.reserve as u128, LAMPORTS_PER_SOL),
+            amount(self.liabilities as u128, LAMPORTS_PER_SOL),
+            self.solvency_percent(),
+            amount(self.total_staked as u128, ONE_FCTR),
+            self.active_stakers,
+            amount(self.bcdev_minted.saturating_sub(previous.bcdev_minted) as u128, ONE_BCDEV),
+            amount(self.bcdev_supply as u128, ONE_BCDEV),
+            amount(self.pending_bcdev, ONE_BCDEV),
+            amount(self.total_entrusted_fctr as u128, ONE_FCTR),
+            self.entrustments,
+            self.overflowed_positions
+        )
+    }
+}
+
+fn amount(base_units: u128, one: u64) -> f64 {
+    base_units as f64 / one as f64
+}
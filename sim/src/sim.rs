@@ -0,0 +1,471 @@
+use std::cmp::min;
+use std::collections::BTreeMap;
+
+use anchor_lang::prelude::*;
+use anchor_lang::error::ErrorCode;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use solana_staking::error::StakingError;
+use solana_staking::math::{self, ONE_FCTR, MAX_ENTRUST_TERM_ROUNDS};
+use solana_staking::state::{Staking, StakerInfo, EntrustRecord, ConfidantOffer, LOCK_TIERS_NUM, STAKING_VERSION, STAKER_INFO_VERSION};
+
+use crate::config::Config;
+use crate::error::SimError;
+use crate::report::Snapshot;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    BuyFctr { amount: u64 },
+    Stake { lock_tier: u8 },
+    Unstake,
+    Entrust { confidant: usize, amount: u64, term_rounds: u64 },
+    DemandBack { confidant: usize },
+    SellFctr { amount: u64 },
+    SellBcdev { amount: u64 }
+}
+
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::BuyFctr { .. } => "buy_fctr",
+            Instruction::Stake { .. } => "stake",
+            Instruction::Unstake => "unstake",
+            Instruction::Entrust { .. } => "entrust",
+            Instruction::DemandBack { .. } => "demand_back",
+            Instruction::SellFctr { .. } => "sell_fctr",
+            Instruction::SellBcdev { .. } => "sell_bcdev"
+        }
+    }
+}
+
+pub struct User {
+    pub info: StakerInfo,
+    // Token account balances, the amounts recorded in the staker info may differ from them
+    pub fctr_balance: u64,
+    pub bcdev_balance: u64,
+    pub offer: Option<ConfidantOffer>
+}
+
+pub struct Entrustment {
+    pub confidant: usize,
+    pub principal: usize,
+    pub record: EntrustRecord
+}
+
+pub struct Sim {
+    pub config: Config,
+    pub staking: Staking,
+    pub users: Vec<User>,
+    pub confidants: Vec<usize>,
+    pub entrustments: Vec<Entrustment>,
+    // Lamports held by the staking account
+    pub reserve: u64,
+    pub bcdev_supply: u64,
+    pub now: u64,
+    pub round: u64,
+    pub applied: BTreeMap<&'static str, u64>,
+    pub rejected: BTreeMap<String, u64>,
+    rng: StdRng
+}
+
+impl Sim {
+    pub fn new(config: Config) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        let mut staking: Staking = zeroed(Staking::LEN);
+        staking.round_time = config.round_time;
+        staking.lock_tiers = config.lock_tiers;
+        staking.entrust_bounds = config.entrust_bounds;
+        staking.version = STAKING_VERSION;
+        staking.total_sol_in = config.initial_reserve;
+        staking.registered_users = config.users as u64;
+
+        let confidants_num = math::bps_share(config.users as u64, config.confidants_bps) as usize;
+        let mut users = Vec::with_capacity(config.users);
+        for i in 0..config.users {
+            let mut info: StakerInfo = zeroed(StakerInfo::LEN);
+            info.staker = Pubkey::new_unique();
+            info.user_rpr = 1;
+            info.version = STAKER_INFO_VERSION;
+
+            let offer = (i < confidants_num).then(|| {
+                let mut offer: ConfidantOffer = zeroed(ConfidantOffer::LEN);
+                offer.confidant = info.staker;
+                offer.accepting = true;
+                offer.max_principals = rng.gen_range(1, 11);
+                offer.commission_bps = rng.gen_range(0, 2_001);
+                offer
+            });
+            users.push(User { info, fctr_balance: 0, bcdev_balance: 0, offer });
+        }
+
+        Sim {
+            reserve: config.initial_reserve,
+            config,
+            staking,
+            users,
+            confidants: (0..confidants_num).collect(),
+            entrustments: vec![],
+            bcdev_supply: 0,
+            now: 0,
+            round: 0,
+            applied: BTreeMap::new(),
+            rejected: BTreeMap::new(),
+            rng
+        }
+    }
+
+    pub fn run(&mut self) -> Vec<Snapshot> {
+        (0..self.config.rounds).map(|_| self.run_round()).collect()
+    }
+
+    // Every user acts once per round in a random order, then the clock moves on to the next round
+    pub fn run_round(&mut self) -> Snapshot {
+        let mut order: Vec<usize> = (0..self.users.len()).collect();
+        order.shuffle(&mut self.rng);
+        for user in order {
+            if let Some(instruction) = self.pick_instruction(user) {
+                let _ = self.execute(user, instruction);
+            }
+        }
+        self.warp(1);
+        self.snapshot()
+    }
+
+    pub fn warp(&mut self, rounds: u64) {
+        self.now += rounds * self.staking.round_time;
+        self.round += rounds;
+    }
+
+    // Runs the instruction the way the program would, a rejected one leaves the state untouched
+    pub fn execute(&mut self, user: usize, instruction: Instruction) -> Result<()> {
+        let result = match instruction {
+            Instruction::BuyFctr { amount } => self.buy_fctr(user, amount),
+            Instruction::Stake { lock_tier } => self.stake(user, lock_tier),
+            Instruction::Unstake => self.unstake(user),
+            Instruction::Entrust { confidant, amount, term_rounds } => self.entrust(user, confidant, amount, term_rounds),
+            Instruction::DemandBack { confidant } => self.demand_back(user, confidant),
+            Instruction::SellFctr { amount } => self.sell_fctr(user, amount),
+            Instruction::SellBcdev { amount } => self.sell_bcdev(user, amount)
+        };
+        match &result {
+            Ok(()) => *self.applied.entry(instruction.name()).or_default() += 1,
+            Err(err) => *self.rejected.entry(error_name(err)).or_default() += 1
+        }
+        result
+    }
+
+    // Users only go for what they could plausibly do, the program still has the final say
+    fn pick_instruction(&mut self, user: usize) -> Option<Instruction> {
+        let weights = &self.config.weights;
+        let table = [
+            weights.buy_fctr, weights.stake, weights.unstake, weights.entrust,
+            weights.demand_back, weights.sell_fctr, weights.sell_bcdev, weights.idle
+        ];
+        let mut roll = self.rng.gen_range(0, weights.total());
+        let choice = table.iter().position(|&weight| {
+            if roll < weight {
+                return true;
+            }
+            roll -= weight;
+            false
+        })?;
+
+        let holder = &self.users[user];
+        match choice {
+            0 => Some(Instruction::BuyFctr { amount: self.rng.gen_range(10, self.config.max_buy_fctr + 1) * ONE_FCTR }),
+            1 => (holder.info.ftcr_amount > 0).then(|| Instruction::Stake { lock_tier: self.rng.gen_range(0, LOCK_TIERS_NUM as u8) }),
+            2 => holder.info.is_staked.then_some(Instruction::Unstake),
+            3 => self.pick_entrust(user),
+            4 => {
+                let confidants: Vec<usize> = self.entrustments.iter().filter(|e| e.principal == user).map(|e| e.confidant).collect();
+                confidants.choose(&mut self.rng).map(|&confidant| Instruction::DemandBack { confidant })
+            },
+            5 => {
+                let amount = min(holder.info.spendable_fctr(), holder.fctr_balance);
+                (amount > 0).then_some(Instruction::SellFctr { amount })
+            },
+            6 => {
+                let amount = min(holder.info.bcdev_amount, holder.bcdev_balance);
+                (amount > 0).then_some(Instruction::SellBcdev { amount })
+            },
+            _ => None
+        }
+    }
+
+    fn pick_entrust(&mut self, principal: usize) -> Option<Instruction> {
+        let info = &self.users[principal].info;
+        let confidant = *self.confidants.choose(&mut self.rng)?;
+        if confidant == principal || info.ftcr_amount == 0 {
+            return None;
+        }
+
+        let bounds = self.staking.entrust_bounds;
+        let min_amount = math::bps_share(info.bought_fctr, bounds.min_fraction_bps);
+        let max_amount = math::bps_share(info.ftcr_amount, bounds.max_fraction_bps);
+        let amount = if min_amount < max_amount { self.rng.gen_range(min_amount, max_amount + 1) } else { min_amount };
+        Some(Instruction::Entrust { confidant, amount, term_rounds: self.rng.gen_range(0, MAX_ENTRUST_TERM_ROUNDS + 1) })
+    }
+
+    // Past u64 the program fails to accrue the reward, the position can't be settled anymore
+    fn check_accrual(&self, user: usize) -> Result<()> {
+        self.accrued_info(user).map(|_| ())
+    }
+
+    // The staker info as accrue_reward would leave it, for the checks the program only fails on after accruing
+    fn accrued_info(&self, user: usize) -> Result<StakerInfo> {
+        let mut info = self.users[user].info.clone();
+        info.accrue_reward(&self.staking, self.now)?;
+        Ok(info)
+    }
+
+    // The mint's supply is a u64 as well, which BCDEV's 18 decimals make easy to run into
+    fn check_bcdev_mint(&self, amount: u64) -> Result<()> {
+        require!(self.bcdev_supply.checked_add(amount).is_some(), SimError::ProgramAbort);
+        Ok(())
+    }
+
+    fn buy_fctr(&mut self, user: usize, amount: u64) -> Result<()> {
+        self.users[user].info.check_buy_fctr(&self.staking, amount)?;
+        self.check_accrual(user)?;
+        check_counter(self.staking.total_fctr_bought_by_users, amount)?;
+
+        let staking = &mut self.staking;
+        let buyer = &mut self.users[user];
+        buyer.info.accrue_reward(staking, self.now)?;
+        buyer.info.bought_fctr += amount;
+        buyer.info.ftcr_amount += amount;
+        buyer.fctr_balance += amount;
+
+        let sol_to_take = math::fctr_buy_price(amount);
+        self.reserve += sol_to_take;
+        staking.total_fctr_bought_by_users += amount;
+        staking.total_sol_in += sol_to_take;
+        buyer.info.lifetime_sol_paid += sol_to_take;
+        Ok(())
+    }
+
+    fn stake(&mut self, user: usize, lock_tier: u8) -> Result<()> {
+        require!(!self.staking.finished, StakingError::StakingFinished);
+        self.check_accrual(user)?;
+
+        let staker = &mut self.users[user];
+        let amount = min(staker.fctr_balance, staker.info.spendable_fctr());
+        staker.info.add_stake(&mut self.staking, lock_tier, amount, self.now)?;
+        staker.info.ftcr_amount = 0;
+        staker.fctr_balance -= amount;
+        Ok(())
+    }
+
+    fn unstake(&mut self, user: usize) -> Result<()> {
+        require!(!self.staking.finished, StakingError::StakingFinished);
+        let is_early_exit = self.users[user].info.check_unstake(&self.staking, self.now)?;
+
+        // Every share is taken out of the stake, the program aborts on a too small one
+        let accrued = self.accrued_info(user)?;
+        let principals: Vec<usize> = self.entrustments.iter().filter(|e| e.confidant == user).map(|e| e.principal).collect();
+        let entrusted: u64 = self.entrustments.iter().filter(|e| e.confidant == user).map(|e| e.record.amount).sum();
+        require!(accrued.stake_size >= entrusted, SimError::ProgramAbort);
+        for &principal in &principals {
+            self.check_accrual(principal)?;
+        }
+        self.check_bcdev_mint(accrued.pending_bcdev_reward)?;
+
+        let (released, kept) = std::mem::take(&mut self.entrustments).into_iter().partition::<Vec<_>, _>(|e| e.confidant == user);
+        self.entrustments = kept;
+
+        let now = self.now;
+        let staking = &mut self.staking;
+        self.users[user].info.accrue_reward(staking, now)?;
+        staking.total_staked -= self.users[user].info.stake_size;
+
+        let mut released_fctr = 0;
+        let mut commission_to_give_to_user = 0;
+        for Entrustment { principal, record, .. } in released {
+            let (confidant, principal) = pair_mut(&mut self.users, user, principal);
+            let (principal_reward, commission) = confidant.info.release_on_unstake(&mut principal.info, staking, &record, now)?;
+            released_fctr += record.amount;
+            commission_to_give_to_user += commission;
+
+            principal.fctr_balance += record.amount;
+            principal.bcdev_balance += principal_reward;
+            self.bcdev_supply += principal_reward;
+        }
+
+        let staker = &mut self.users[user];
+        let (amount_to_give_to_user, reward_to_give_to_user) = staker.info.finish_unstake(staking, released_fctr, commission_to_give_to_user, is_early_exit, now);
+        staker.fctr_balance += amount_to_give_to_user;
+        staker.bcdev_balance += reward_to_give_to_user;
+        self.bcdev_supply += reward_to_give_to_user;
+        Ok(())
+    }
+
+    fn entrust(&mut self, principal: usize, confidant: usize, amount: u64, term_rounds: u64) -> Result<()> {
+        require!(principal != confidant, StakingError::SelfEntrust);
+        let Some(offer) = &self.users[confidant].offer else {
+            return err!(ErrorCode::AccountNotInitialized);
+        };
+        require!(!self.entrustments.iter().any(|e| e.confidant == confidant && e.principal == principal), SimError::EntrustRecordExists);
+
+        let principal_user = &self.users[principal];
+        principal_user.info.check_entrust(&self.users[confidant].info, offer, &self.staking, amount, term_rounds, principal_user.fctr_balance)?;
+        self.check_accrual(principal)?;
+        self.check_accrual(confidant)?;
+
+        let commission_bps = offer.commission_bps;
+        let now = self.now;
+        let staking = &mut self.staking;
+        let (principal_user, confidant_user) = pair_mut(&mut self.users, principal, confidant);
+        principal_user.fctr_balance -= amount;
+
+        let record = EntrustRecord {
+            confidant: confidant_user.info.staker,
+            principal: principal_user.info.staker,
+            amount,
+            commission_bps,
+            rpr_boost: math::term_rpr_boost(term_rounds),
+            term_rounds,
+            term_end: now + term_rounds * staking.round_time,
+            bump: 0
+        };
+        confidant_user.info.take_entrustment(&mut principal_user.info, staking, &record, now)?;
+
+        self.entrustments.push(Entrustment { confidant, principal, record });
+        Ok(())
+    }
+
+    fn demand_back(&mut self, principal: usize, confidant: usize) -> Result<()> {
+        let Some(position) = self.entrustments.iter().position(|e| e.confidant == confidant && e.principal == principal) else {
+            return err!(ErrorCode::AccountNotInitialized);
+        };
+        let record = &self.entrustments[position].record;
+        require!(record.amount > 0, StakingError::NothingToDemandBack);
+        require!(self.now >= record.term_end, StakingError::EntrustTermNotOver);
+
+        self.return_entrustment(position)
+    }
+
+    // The bookkeeping of utils::return_entrustment along with the token balances its transfers change
+    fn return_entrustment(&mut self, position: usize) -> Result<()> {
+        let Entrustment { confidant, principal, .. } = self.entrustments[position];
+        let amount = self.entrustments[position].record.amount;
+
+        // The confidant's stake is taken down by the amount, the program aborts if it doesn't cover it
+        let accrued = self.accrued_info(confidant)?;
+        require!(!accrued.is_staked || accrued.stake_size >= amount, SimError::ProgramAbort);
+        self.check_accrual(principal)?;
+        if accrued.is_staked {
+            self.check_bcdev_mint(accrued.pending_bcdev_reward)?;
+        }
+
+        let Entrustment { record, .. } = self.entrustments.remove(position);
+        let (confidant_user, principal_user) = pair_mut(&mut self.users, confidant, principal);
+        let reward_split = confidant_user.info.return_entrustment(&mut principal_user.info, &mut self.staking, &record, self.now)?;
+
+        principal_user.fctr_balance += amount;
+        if let Some((principal_reward, _commission)) = reward_split {
+            principal_user.bcdev_balance += principal_reward;
+            self.bcdev_supply += principal_reward;
+        }
+        Ok(())
+    }
+
+    fn sell_fctr(&mut self, user: usize, amount: u64) -> Result<()> {
+        let seller = &self.users[user];
+        seller.info.check_sell_fctr(&self.staking, amount, seller.fctr_balance)?;
+
+        let sol_to_give = math::fctr_sell_price(amount);
+        require!(self.reserve >= sol_to_give, StakingError::NotEnoughFunds);
+        check_counter(self.staking.total_fctr_sold_by_users, amount)?;
+
+        let seller = &mut self.users[user];
+        self.reserve -= sol_to_give;
+        self.staking.total_sol_out += sol_to_give;
+        seller.fctr_balance -= amount;
+        self.staking.total_fctr_sold_by_users += amount;
+        seller.info.ftcr_amount -= amount;
+        seller.info.lifetime_sol_received += sol_to_give;
+        Ok(())
+    }
+
+    fn sell_bcdev(&mut self, user: usize, amount: u64) -> Result<()> {
+        let seller = &self.users[user];
+        seller.info.check_sell_bcdev(&self.staking, amount, seller.bcdev_balance)?;
+
+        // The program doesn't check the reserve here, the lamports transfer fails instead
+        let sol_to_give = math::bcdev_sell_price(amount);
+        require!(self.reserve >= sol_to_give, StakingError::NotEnoughFunds);
+        check_counter(self.staking.total_bcdev_sold_by_users, amount)?;
+
+        let seller = &mut self.users[user];
+        self.reserve -= sol_to_give;
+        self.staking.total_sol_out += sol_to_give;
+        seller.bcdev_balance -= amount;
+        self.bcdev_supply -= amount;
+        self.staking.total_bcdev_sold_by_users += amount;
+        seller.info.bcdev_amount -= amount;
+        seller.info.lifetime_sol_received += sol_to_give;
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        // Everything users could sell back at once at the program's prices, staked and entrusted FCTR included
+        let fctr_outstanding = self.staking.total_fctr_bought_by_users - self.staking.total_fctr_sold_by_users;
+        let mut liabilities = math::fctr_sell_price(fctr_outstanding);
+        let mut pending_bcdev = 0;
+        let mut overflowed_positions = 0;
+        for (user, holder) in self.users.iter().enumerate() {
+            liabilities += math::bcdev_sell_price(holder.bcdev_balance);
+            match self.accrued_info(user) {
+                Ok(info) => {
+                    pending_bcdev += info.pending_bcdev_reward as u128;
+                    liabilities += math::bcdev_sell_price(info.pending_bcdev_reward);
+                },
+                Err(_) => overflowed_positions += 1
+            }
+        }
+
+        Snapshot {
+            round: self.round,
+            reserve: self.reserve,
+            liabilities,
+            total_staked: self.staking.total_staked,
+            active_stakers: self.staking.active_stakers,
+            bcdev_minted: self.staking.total_bcdev_minted,
+            bcdev_supply: self.bcdev_supply,
+            pending_bcdev,
+            total_entrusted_fctr: self.staking.total_entrusted_fctr,
+            entrustments: self.entrustments.len(),
+            overflowed_positions
+        }
+    }
+}
+
+// The program's trade totals are plain u64 sums, once one of them overflows the instruction aborts for good
+fn check_counter(counter: u64, amount: u64) -> Result<()> {
+    require!(counter.checked_add(amount).is_some(), SimError::ProgramAbort);
+    Ok(())
+}
+
+fn error_name(err: &Error) -> String {
+    match err {
+        Error::AnchorError(err) => err.error_name.clone(),
+        Error::ProgramError(err) => err.program_error.to_string()
+    }
+}
+
+// Accounts are zero-filled before the handler sets them up, same as `init` does on-chain
+fn zeroed<T: AccountDeserialize>(len: usize) -> T {
+    T::try_deserialize_unchecked(&mut &vec![0; 8 + len][..]).unwrap()
+}
+
+// The program gets two distinct accounts, borrowed here out of the one list
+fn pair_mut(users: &mut [User], first: usize, second: usize) -> (&mut User, &mut User) {
+    assert_ne!(first, second);
+    if first < second {
+        let (head, tail) = users.split_at_mut(second);
+        (&mut head[first], &mut tail[0])
+    } else {
+        let (head, tail) = users.split_at_mut(first);
+        (&mut tail[0], &mut head[second])
+    }
+}
@@ -0,0 +1,10 @@
+use staking_sim::Config;
+
+#[test]
+fn values_the_simulation_cant_run_with_are_rejected() {
+    assert!(Config::default().validate().is_ok());
+    assert!(Config { round_time: 0, ..Config::default() }.validate().is_err());
+    assert!(Config { confidants_bps: 10_001, ..Config::default() }.validate().is_err());
+    assert!(Config { max_buy_fctr: 9, ..Config::default() }.validate().is_err());
+    assert!(Config { confidants_bps: 10_000, max_buy_fctr: 10, ..Config::default() }.validate().is_ok());
+}
@@ -0,0 +1,134 @@
+// Random instruction sequences against the simulator, checking after every step that the intertwined
+// staking, entrust and reserve bookkeeping still adds up
+
+use proptest::prelude::*;
+use solana_staking::math::{self, ONE_FCTR, MAX_ENTRUST_TERM_ROUNDS};
+use solana_staking::state::LOCK_TIERS_NUM;
+use staking_sim::{Config, Instruction, Sim};
+
+const USERS: usize = 6;
+// Users that publish an offer come first
+const CONFIDANTS: usize = 3;
+
+// Amounts are fractions of what the user holds when the step runs, so that most of them get past the checks
+#[derive(Clone, Debug)]
+enum Step {
+    BuyFctr { user: usize, fctr: u64 },
+    Stake { user: usize, lock_tier: u8 },
+    Unstake { user: usize },
+    Entrust { user: usize, confidant: usize, fraction_bps: u64, term_rounds: u64 },
+    // Picks one of the open entrustments
+    DemandBack { pick: usize },
+    SellFctr { user: usize, fraction_bps: u64 },
+    SellBcdev { user: usize, fraction_bps: u64 },
+    Warp { rounds: u64 }
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    let user = 0..USERS;
+    prop_oneof![
+        3 => (user.clone(), 10..=1_000u64).prop_map(|(user, fctr)| Step::BuyFctr { user, fctr }),
+        2 => (user.clone(), 0..LOCK_TIERS_NUM as u8).prop_map(|(user, lock_tier)| Step::Stake { user, lock_tier }),
+        2 => user.clone().prop_map(|user| Step::Unstake { user }),
+        4 => (user.clone(), 0..CONFIDANTS, 2_500..=5_000u64, 0..=MAX_ENTRUST_TERM_ROUNDS)
+            .prop_map(|(user, confidant, fraction_bps, term_rounds)| Step::Entrust { user, confidant, fraction_bps, term_rounds }),
+        2 => any::<usize>().prop_map(|pick| Step::DemandBack { pick }),
+        1 => (user.clone(), 1..=12_000u64).prop_map(|(user, fraction_bps)| Step::SellFctr { user, fraction_bps }),
+        1 => (user, 1..=12_000u64).prop_map(|(user, fraction_bps)| Step::SellBcdev { user, fraction_bps }),
+        2 => (1..=15u64).prop_map(|rounds| Step::Warp { rounds })
+    ]
+}
+
+fn new_sim(seed: u64) -> Sim {
+    // The default funding covers the 109/101 spread of the volumes traded here
+    let confidants_bps = (CONFIDANTS * 10_000 / USERS) as u64;
+    Sim::new(Config { users: USERS, confidants_bps, seed, ..Config::default() })
+}
+
+fn apply(sim: &mut Sim, step: &Step) -> Result<(), TestCaseError> {
+    let (user, instruction) = match *step {
+        Step::BuyFctr { user, fctr } => (user, Instruction::BuyFctr { amount: fctr * ONE_FCTR }),
+        Step::Stake { user, lock_tier } => (user, Instruction::Stake { lock_tier }),
+        Step::Unstake { user } => (user, Instruction::Unstake),
+        Step::Entrust { user, confidant, fraction_bps, term_rounds } => {
+            let amount = math::bps_share(sim.users[user].info.ftcr_amount, fraction_bps);
+            (user, Instruction::Entrust { confidant, amount, term_rounds })
+        },
+        Step::DemandBack { pick } => {
+            if sim.entrustments.is_empty() {
+                return Ok(());
+            }
+            let entrustment = &sim.entrustments[pick % sim.entrustments.len()];
+            let (principal, confidant, term_end) = (entrustment.principal, entrustment.confidant, entrustment.record.term_end);
+
+            // Whatever the confidant did in the meantime, the principal gets their FCTR back once the term is over
+            let result = sim.execute(principal, Instruction::DemandBack { confidant });
+            prop_assert!(sim.now < term_end || result.is_ok(), "demand_back failed after the term: {:?}", result);
+            return Ok(());
+        },
+        Step::SellFctr { user, fraction_bps } => {
+            let amount = math::bps_share(sim.users[user].fctr_balance, fraction_bps);
+            (user, Instruction::SellFctr { amount })
+        },
+        Step::SellBcdev { user, fraction_bps } => {
+            let amount = math::bps_share(sim.users[user].bcdev_balance, fraction_bps);
+            (user, Instruction::SellBcdev { amount })
+        },
+        Step::Warp { rounds } => {
+            sim.warp(rounds);
+            return Ok(());
+        }
+    };
+    let _ = sim.execute(user, instruction);
+    Ok(())
+}
+
+fn check_invariants(sim: &Sim) -> Result<(), TestCaseError> {
+    let staking = &sim.staking;
+    let snapshot = sim.snapshot();
+
+    prop_assert_eq!(sim.reserve, staking.total_sol_in - staking.total_sol_out, "reserve doesn't match the SOL totals");
+    prop_assert!(snapshot.reserve >= snapshot.liabilities, "reserve {} can't cover liabilities {}", snapshot.reserve, snapshot.liabilities);
+    prop_assert_eq!(sim.bcdev_supply, staking.total_bcdev_minted - staking.total_bcdev_sold_by_users, "BCDEV supply doesn't match the totals");
+    prop_assert_eq!(sim.bcdev_supply, sim.users.iter().map(|user| user.bcdev_balance).sum::<u64>(), "BCDEV supply doesn't match the balances");
+
+    let stakers = sim.users.iter().filter(|user| user.info.is_staked);
+    prop_assert_eq!(staking.total_staked, stakers.clone().map(|user| user.info.stake_size).sum::<u64>(), "total_staked doesn't match the stakes");
+    prop_assert_eq!(staking.active_stakers, stakers.count() as u64, "active_stakers doesn't match the stakers");
+    prop_assert_eq!(staking.total_entrusted_fctr, sim.entrustments.iter().map(|e| e.record.amount).sum::<u64>(), "total_entrusted_fctr doesn't match the records");
+
+    // Every FCTR bought and not sold back is either held, staked or in custody for an unstaked confidant
+    let held: u64 = sim.users.iter().map(|user| user.fctr_balance + user.info.stake_size).sum();
+    let custodied: u64 = sim.entrustments.iter().filter(|e| !sim.users[e.confidant].info.is_staked).map(|e| e.record.amount).sum();
+    prop_assert_eq!(staking.total_fctr_bought_by_users - staking.total_fctr_sold_by_users, held + custodied, "FCTR went missing");
+
+    for (index, user) in sim.users.iter().enumerate() {
+        let info = &user.info;
+        let as_confidant = sim.entrustments.iter().filter(|e| e.confidant == index);
+        let as_principal = sim.entrustments.iter().filter(|e| e.principal == index);
+
+        prop_assert_eq!(info.principals_count, as_confidant.clone().count() as u64, "principals_count of user {}", index);
+        prop_assert_eq!(info.entrusted_fctr, as_confidant.map(|e| e.record.amount).sum::<u64>(), "entrusted_fctr of user {}", index);
+        prop_assert!(!info.is_staked || info.entrusted_fctr <= info.stake_size, "user {} holds more entrusted FCTR than their stake", index);
+        prop_assert_eq!(info.entrustments_count, as_principal.clone().count() as u64, "entrustments_count of user {}", index);
+        prop_assert_eq!(info.user_rpr, 1 + as_principal.map(|e| e.record.rpr_boost).sum::<u64>(), "user_rpr of user {}", index);
+        prop_assert_eq!(info.is_in_trust_program, info.principals_count > 0 || info.entrustments_count > 0, "trust status of user {}", index);
+        prop_assert!(info.is_staked || info.stake_size == 0, "user {} has a stake without being staked", index);
+        // The recorded FCTR is what the user holds plus, for an unstaked confidant, the custody credit it can't spend
+        prop_assert!(info.custodied_fctr() <= info.ftcr_amount, "user {} spent the custody credit", index);
+        prop_assert!(info.ftcr_amount <= user.fctr_balance + info.custodied_fctr(), "user {} is credited with FCTR they don't hold", index);
+    }
+    Ok(())
+}
+
+// The default config runs 256 cases and picks up PROPTEST_CASES, which an explicit case count would override
+proptest! {
+    #[test]
+    fn bookkeeping_holds(seed in any::<u64>(), steps in prop::collection::vec(step(), 1..80)) {
+        let mut sim = new_sim(seed);
+        for step in &steps {
+            apply(&mut sim, step)?;
+            check_invariants(&sim)?;
+        }
+    }
+}
@@ -1,7 +1,7 @@
 import * as anchor from "@project-serum/anchor";
 import { Program } from "@project-serum/anchor";
 import { SolanaStaking } from "../target/types/solana_staking";
-import { Account, createMint, getAccount, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID } from '@solana/spl-token';
+import { Account, createMint, getAccount, getOrCreateAssociatedTokenAccount, mintTo, transfer, TOKEN_PROGRAM_ID } from '@solana/spl-token';
 import { utf8 } from "@project-serum/anchor/dist/cjs/utils/bytes";
 import { expect } from "chai";
 import { BN } from "bn.js";
@@ -18,7 +18,7 @@ describe("solana_staking", () => {
   const proofSigner = anchor.web3.Keypair.generate();
 
 
-  const testRoundTime = new anchor.BN(60 * 60 * 24);
+  const testRoundTime = new anchor.BN(1);
   let fctrMint: anchor.web3.PublicKey;
   let bcdevMint: anchor.web3.PublicKey;
 
@@ -26,8 +26,105 @@ describe("solana_staking", () => {
   let stakingBcdevAccount: Account;
 
   let stakingPda: anchor.web3.PublicKey;
+  let fctrVault: anchor.web3.PublicKey;
   const ONE_FCTR = new BN(10).pow(new BN(12));
   const ONE_BCDEV = new BN(10).pow(new BN(18));
+  const BONUS_PRECISION = new BN(10).pow(new BN(12));
+
+  type TestStaker = { user: anchor.web3.Keypair, stakerInfo: anchor.web3.PublicKey, fctrAccount: anchor.web3.PublicKey, bcdevAccount: anchor.web3.PublicKey };
+
+  // A freshly registered wallet holding the FCTR it bought
+  async function newStaker(fctrAmount: BN): Promise<TestStaker> {
+    const user = anchor.web3.Keypair.generate();
+    await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(user.publicKey, 100 * anchor.web3.LAMPORTS_PER_SOL));
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), user.publicKey.toBuffer()], program.programId);
+
+    await program.methods.register().accounts({
+      staker: user.publicKey,
+      stakerInfo: stakerInfo,
+      staking: stakingPda,
+      proofSigner: proofSigner.publicKey
+    }).signers([user, proofSigner]).rpc()
+
+    const fctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, user.publicKey);
+    const bcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, user.publicKey);
+    await program.methods.buyFctr(fctrAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: user.publicKey,
+      stakerInfo: stakerInfo,
+      userFctrAccount: fctrAccount.address
+    }).signers([user]).rpc();
+
+    return { user, stakerInfo, fctrAccount: fctrAccount.address, bcdevAccount: bcdevAccount.address };
+  }
+
+  async function stakeAs(staker: TestStaker, lockTier: number) {
+    await program.methods.stake(lockTier).accounts({
+      staking: stakingPda,
+      stakerInfo: staker.stakerInfo,
+      stakerFctrAccount: staker.fctrAccount,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: staker.user.publicKey
+    }).signers([staker.user]).rpc();
+  }
+
+  async function unstakeAs(staker: TestStaker, principalAccounts: anchor.web3.AccountMeta[] = []) {
+    return await program.methods.unstake().accounts({
+      staking: stakingPda,
+      stakerInfo: staker.stakerInfo,
+      stakerFctrAccount: staker.fctrAccount,
+      stakerBcdevAccount: staker.bcdevAccount,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: staker.user.publicKey
+    }).remainingAccounts(principalAccounts).signers([staker.user]).rpc({ commitment: "confirmed" });
+  }
+
+  // Entrusts `amount` out of the principal's FCTR to a confidant with a published offer
+  async function entrustAs(principal: TestStaker, confidant: TestStaker, amount: BN) {
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.user.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.user.publicKey.toBuffer(), principal.user.publicKey.toBuffer()], program.programId);
+
+    await program.methods.entrust(confidant.user.publicKey, amount, new BN(0)).accounts({
+      staking: stakingPda,
+      principal: principal.user.publicKey,
+      principalInfo: principal.stakerInfo,
+      confidantInfo: confidant.stakerInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principal.fctrAccount,
+      fctrVault: fctrVault
+    }).signers([principal.user]).rpc();
+    return entrustRecord;
+  }
+
+  async function publishOfferAs(confidant: TestStaker, commissionBps: number) {
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.user.publicKey.toBuffer()], program.programId);
+    await program.methods.publishOffer(true, new BN(10), new BN(commissionBps), new BN(0)).accounts({
+      confidantInfo: confidant.stakerInfo,
+      confidantOffer: confidantOffer,
+      confidant: confidant.user.publicKey
+    }).signers([confidant.user]).rpc()
+  }
+
+  async function rewardSplits(tx: string) {
+    const logs = (await program.provider.connection.getTransaction(tx, { commitment: "confirmed" })).meta.logMessages;
+    const splits = [];
+    new anchor.EventParser(program.programId, program.coder).parseLogs(logs, event => {
+      if (event.name == "RewardSplit") {
+        splits.push(event.data);
+      }
+    });
+    return splits;
+  }
+
+  async function tokenBalance(tokenAccount: anchor.web3.PublicKey) {
+    return new BN((await getAccount(program.provider.connection, tokenAccount)).amount.toString());
+  }
 
 
   it("Is initialized!", async () => {
@@ -35,6 +132,7 @@ describe("solana_staking", () => {
     await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(confidant.publicKey, 100000 * anchor.web3.LAMPORTS_PER_SOL));
 
     [stakingPda,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staking")], program.programId);
+    [fctrVault,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("fctr-vault")], program.programId);
 
     fctrMint = await createMint(program.provider.connection, payer, stakingPda, null, 12);
     bcdevMint = await createMint(program.provider.connection, payer, stakingPda, null, 18);
@@ -49,6 +147,89 @@ describe("solana_staking", () => {
     console.log("Your transaction signature", tx);
   });
 
+  it("Test lock tiers configuration", async () => {
+    const tiers = [[1, 100], [3, 125], [6, 150], [12, 200]].map(([rounds, multiplier]) => ({ rounds: new BN(rounds), multiplier: new BN(multiplier) }));
+
+    try {
+      await program.methods.setLockTiers([tiers[1], tiers[0], tiers[2], tiers[3]]).accounts({
+        staking: stakingPda,
+        owner: owner.publicKey,
+      }).rpc();
+      expect.fail("Unordered tiers accepted");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('InvalidLockTiers');
+    }
+
+    await program.methods.setLockTiers(tiers).accounts({
+      staking: stakingPda,
+      owner: owner.publicKey,
+    }).rpc();
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.lockTiers[3].multiplier.toNumber()).to.equal(200);
+  })
+
+  it("Test early exit configuration", async () => {
+    try {
+      await program.methods.setEarlyExit(true, new BN(10001), { stake: {} }, { rewardPool: {} }).accounts({
+        staking: stakingPda,
+        owner: owner.publicKey,
+      }).rpc();
+      expect.fail("Penalty above 100% accepted");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('InvalidPenalty');
+    }
+
+    await program.methods.setEarlyExit(false, new BN(1000), { stake: {} }, { rewardPool: {} }).accounts({
+      staking: stakingPda,
+      owner: owner.publicKey,
+    }).rpc();
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.earlyExitPenaltyBps.toNumber()).to.equal(1000);
+  })
+
+  it("Test entrust bounds configuration", async () => {
+    try {
+      await program.methods.setEntrustBounds({ minFractionBps: new BN(6000), maxFractionBps: new BN(5000), minDepositRatioBps: new BN(5000), maxDepositRatioBps: new BN(20000) }).accounts({
+        staking: stakingPda,
+        owner: owner.publicKey,
+      }).rpc();
+      expect.fail("Inverted bounds accepted");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('InvalidEntrustBounds');
+    }
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.entrustBounds.maxFractionBps.toNumber()).to.equal(5000);
+  })
+
+  it("Test migrating an up to date account", async () => {
+    try {
+      await program.methods.migrateStaking().accounts({
+        staking: stakingPda,
+        owner: owner.publicKey,
+      }).rpc();
+      expect.fail("Migrated an up to date account");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('AlreadyMigrated');
+    }
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.version).to.equal(1);
+  })
+
+  it("Test arbiter configuration", async () => {
+    const arbiter = anchor.web3.Keypair.generate();
+    await program.methods.setArbiter(arbiter.publicKey).accounts({
+      staking: stakingPda,
+      owner: owner.publicKey,
+    }).rpc();
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.arbiter.equals(arbiter.publicKey)).to.be.true;
+  })
+
   it("Test user registration", async () => {
     const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
 
@@ -58,6 +239,9 @@ describe("solana_staking", () => {
       staking: stakingPda,
       proofSigner: proofSigner.publicKey
     }).signers([proofSigner]).rpc()
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.registeredUsers.toNumber()).to.equal(1);
   })
 
   it("Test fctr buying", async () => {
@@ -168,11 +352,12 @@ describe("solana_staking", () => {
 
     expect(userFctrAccount.amount > 0).to.be.true;
 
-    await program.methods.stake().accounts({
+    await program.methods.stake(0).accounts({
       staking: stakingPda,
       stakerInfo: stakerInfo,
       stakerFctrAccount: userFctrAccount.address,
-      fctrMint: fctrMint
+      fctrMint: fctrMint,
+      fctrVault: fctrVault
     }).rpc();
 
     userFctrAccount = await getAccount(program.provider.connection, userFctrAccount.address);
@@ -196,6 +381,7 @@ describe("solana_staking", () => {
       stakerBcdevAccount: userBcdevAccount.address,
       bcdevMint: bcdevMint,
       fctrMint: fctrMint,
+      fctrVault: fctrVault
     }).rpc();
 
     userFctrAccount = await getAccount(program.provider.connection, userFctrAccount.address);
@@ -205,12 +391,46 @@ describe("solana_staking", () => {
     expect(userBcdevAccount.amount > BigInt(0)).to.be.true;
   });
 
+  it("Test unstaking in the round of staking", async () => {
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const userFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    const userBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+
+    // Both in one transaction, so that they see the same clock
+    const stake = await program.methods.stake(0).accounts({
+      staking: stakingPda,
+      stakerInfo: stakerInfo,
+      stakerFctrAccount: userFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault
+    }).instruction();
+
+    try {
+      await program.methods.unstake().accounts({
+        staking: stakingPda,
+        stakerInfo: stakerInfo,
+        stakerFctrAccount: userFctrAccount.address,
+        stakerBcdevAccount: userBcdevAccount.address,
+        bcdevMint: bcdevMint,
+        fctrMint: fctrMint,
+        fctrVault: fctrVault
+      }).preInstructions([stake]).rpc();
+      expect.fail("Unstaked in the round of staking");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('CantUnstakeInThisVeryRound');
+    }
+    expect((await program.account.stakerInfo.fetch(stakerInfo)).isStaked).to.be.false;
+  });
+
   it("Test entrusting", async () => {
     const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
     const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
 
     let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
     let confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
 
     await program.methods.register().accounts({
       staker: confidant.publicKey,
@@ -218,14 +438,49 @@ describe("solana_staking", () => {
       staking: stakingPda,
       proofSigner: proofSigner.publicKey
     }).signers([confidant, proofSigner]).rpc()
+
+    await program.methods.publishOffer(true, new BN(10), new BN(500), new BN(0)).accounts({
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      confidant: confidant.publicKey
+    }).signers([confidant]).rpc()
+
+    const [ownerOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), owner.publicKey.toBuffer()], program.programId);
+    const [selfRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), owner.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    await program.methods.publishOffer(true, new BN(10), new BN(500), new BN(0)).accounts({
+      confidantInfo: principalInfo,
+      confidantOffer: ownerOffer,
+      confidant: owner.publicKey
+    }).rpc()
+
     try {
-      await program.methods.entrust(confidant.publicKey).accounts({
+      await program.methods.entrust(owner.publicKey, entrustAmount, new BN(0)).accounts({
+        staking: stakingPda,
+        principal: owner.publicKey,
+        principalInfo: principalInfo,
+        confidantInfo: principalInfo,
+        confidantOffer: ownerOffer,
+        entrustRecord: selfRecord,
+        fctrMint: fctrMint,
+        principalFctrAccount: principalFctrAccount.address,
+        fctrVault: fctrVault
+      }).rpc();
+      expect.fail("Entrusted to oneself");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('SelfEntrust');
+    }
+
+    try {
+      await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
         staking: stakingPda,
         principal: owner.publicKey,
         principalInfo: principalInfo,
         confidantInfo: confidantInfo,
+        confidantOffer: confidantOffer,
+        entrustRecord: entrustRecord,
         fctrMint: fctrMint,
-        principalFctrAccount: principalFctrAccount.address
+        principalFctrAccount: principalFctrAccount.address,
+        fctrVault: fctrVault
       }).rpc();
     } catch (e) {
       expect(e.error.errorCode.code).to.equal('InvalidDepositDiff'); // Доверять и принимать FCTR-токены можно только от участника с депозитом от 50 до 200% от собственного(купившим с платформы от половины до двух частей).
@@ -239,31 +494,893 @@ describe("solana_staking", () => {
       userFctrAccount: confidantFctrAccount.address
     }).signers([confidant]).rpc();
 
-    await program.methods.entrust(confidant.publicKey).accounts({
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
       staking: stakingPda,
       principal: owner.publicKey,
       principalInfo: principalInfo,
       confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
       fctrMint: fctrMint,
-      principalFctrAccount: principalFctrAccount.address
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
     }).rpc();
   });
 
   it("Test demanding back", async () => {
     const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
     const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
 
     let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
     let confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    let principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
 
     await program.methods.demandBack(confidant.publicKey).accounts({
       staking: stakingPda,
       principal: owner.publicKey,
       principalInfo: principalInfo,
       confidantInfo: confidantInfo,
+      entrustRecord: entrustRecord,
       fctrMint: fctrMint,
       principalFctrAccount: principalFctrAccount.address,
-    })
+      bcdevMint: bcdevMint,
+      principalBcdevAccount: principalBcdevAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    expect(await program.account.entrustRecord.fetchNullable(entrustRecord)).to.be.null;
+    expect((await program.account.stakerInfo.fetch(principalInfo)).isInTrustProgram).to.be.false;
+    expect((await program.account.stakerInfo.fetch(confidantInfo)).isInTrustProgram).to.be.false;
+  })
+
+  it("Test releasing a principal", async () => {
+    const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
+
+    let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    let principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
+
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(3)).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    try {
+      await program.methods.demandBack(confidant.publicKey).accounts({
+        staking: stakingPda,
+        principal: owner.publicKey,
+        principalInfo: principalInfo,
+        confidantInfo: confidantInfo,
+        entrustRecord: entrustRecord,
+        fctrMint: fctrMint,
+        principalFctrAccount: principalFctrAccount.address,
+        bcdevMint: bcdevMint,
+        principalBcdevAccount: principalBcdevAccount.address,
+        fctrVault: fctrVault
+      }).rpc();
+      expect.fail("Demanded back during the term");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('EntrustTermNotOver');
+    }
+    expect((await program.account.entrustRecord.fetch(entrustRecord)).rprBoost.toNumber()).to.equal(3);
+
+    await program.methods.releasePrincipal(owner.publicKey).accounts({
+      staking: stakingPda,
+      confidant: confidant.publicKey,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      bcdevMint: bcdevMint,
+      principalBcdevAccount: principalBcdevAccount.address,
+      fctrVault: fctrVault
+    }).signers([confidant]).rpc();
+
+    principalFctrAccount = await getAccount(program.provider.connection, principalFctrAccount.address);
+    expect(principalFctrAccount.amount == BigInt(entrustAmount.mul(new BN(2)).toString(10))).to.be.true;
+    expect(await program.account.entrustRecord.fetchNullable(entrustRecord)).to.be.null;
+    const confidantStats = await program.account.stakerInfo.fetch(confidantInfo);
+    expect(confidantStats.principalsCount.toNumber()).to.equal(0);
+    expect(confidantStats.confidantPrincipalsServed.toNumber()).to.equal(2);
+    expect(confidantStats.confidantTotalReturned.eq(confidantStats.confidantTotalEntrusted)).to.be.true;
+  })
+
+  it("Test staking with entrusted fctr in custody", async () => {
+    const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
+
+    let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    let principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    let confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    let confidantBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, confidant.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
+
+    // Brings the confidant's recorded FCTR back to what they hold after the earlier entrustments
+    await program.methods.syncBalances(confidant.publicKey).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address
+    }).rpc();
+
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    await program.methods.stake(0).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).signers([confidant]).rpc();
+
+    // The entrusted FCTR joins the stake, so that the principal can still get it back out of it
+    const confidantStake = (await program.account.stakerInfo.fetch(confidantInfo)).stakeSize;
+    expect(confidantStake.eq(entrustAmount.add(new BN(confidantFctrAccount.amount.toString())))).to.be.true;
+
+    await new Promise(r => setTimeout(r, 2000));
+
+    await program.methods.unstake().accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).remainingAccounts([
+      { pubkey: principalFctrAccount.address, isWritable: true, isSigner: false },
+      { pubkey: principalBcdevAccount.address, isWritable: true, isSigner: false },
+      { pubkey: principalInfo, isWritable: true, isSigner: false },
+      { pubkey: entrustRecord, isWritable: true, isSigner: false },
+      { pubkey: owner.publicKey, isWritable: true, isSigner: false }
+    ]).signers([confidant]).rpc();
+
+    principalFctrAccount = await getAccount(program.provider.connection, principalFctrAccount.address);
+    confidantFctrAccount = await getAccount(program.provider.connection, confidantFctrAccount.address);
+    expect(principalFctrAccount.amount == BigInt(entrustAmount.mul(new BN(2)).toString(10))).to.be.true;
+    expect(confidantFctrAccount.amount == BigInt(confidantStake.sub(entrustAmount).toString(10))).to.be.true;
+  })
+
+  it("Test demanding back from a staked confidant", async () => {
+    const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
+
+    let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    let principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    let confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    let confidantBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, confidant.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
+
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    // Staking takes the confidant's recorded FCTR down to zero
+    await program.methods.stake(0).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).signers([confidant]).rpc();
+    const confidantStake = (await program.account.stakerInfo.fetch(confidantInfo)).stakeSize;
+
+    await program.methods.demandBack(confidant.publicKey).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      bcdevMint: bcdevMint,
+      principalBcdevAccount: principalBcdevAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    principalFctrAccount = await getAccount(program.provider.connection, principalFctrAccount.address);
+    expect(principalFctrAccount.amount == BigInt(entrustAmount.mul(new BN(2)).toString(10))).to.be.true;
+    expect((await program.account.stakerInfo.fetch(confidantInfo)).stakeSize.eq(confidantStake.sub(entrustAmount))).to.be.true;
+
+    await new Promise(r => setTimeout(r, 2000));
+
+    await program.methods.unstake().accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).signers([confidant]).rpc();
+  })
+
+  it("Test settling principals ahead of unstaking", async () => {
+    const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
+
+    let principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    let principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    let confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    let confidantBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, confidant.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
+
+    await program.methods.syncBalances(confidant.publicKey).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address
+    }).rpc();
+
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    await program.methods.stake(0).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).signers([confidant]).rpc();
+
+    await program.methods.settlePrincipals().accounts({
+      staking: stakingPda,
+      confidantInfo: confidantInfo,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      confidant: confidant.publicKey
+    }).remainingAccounts([
+      { pubkey: principalFctrAccount.address, isWritable: true, isSigner: false },
+      { pubkey: principalBcdevAccount.address, isWritable: true, isSigner: false },
+      { pubkey: principalInfo, isWritable: true, isSigner: false },
+      { pubkey: entrustRecord, isWritable: true, isSigner: false },
+      { pubkey: owner.publicKey, isWritable: true, isSigner: false }
+    ]).signers([confidant]).rpc();
+
+    principalFctrAccount = await getAccount(program.provider.connection, principalFctrAccount.address);
+    expect(principalFctrAccount.amount == BigInt(entrustAmount.mul(new BN(2)).toString(10))).to.be.true;
+    expect(await program.account.entrustRecord.fetchNullable(entrustRecord)).to.be.null;
+    expect((await program.account.stakerInfo.fetch(confidantInfo)).principalsCount.toNumber()).to.equal(0);
+
+    await new Promise(r => setTimeout(r, 2000));
+
+    // With every principal settled the final unstake takes no principal accounts
+    await program.methods.unstake().accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      staker: confidant.publicKey
+    }).signers([confidant]).rpc();
+    expect((await program.account.stakerInfo.fetch(confidantInfo)).isStaked).to.be.false;
+  })
+
+  it("Test switching to escrow mode", async () => {
+    const [principalInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const [entrustRecord,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("entrust"), confidant.publicKey.toBuffer(), owner.publicKey.toBuffer()], program.programId);
+    const [confidantOffer,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("confidant-offer"), confidant.publicKey.toBuffer()], program.programId);
+
+    const principalFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    const principalBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    const entrustAmount = new BN((principalFctrAccount.amount / BigInt(2)).toString());
+
+    // FCTR entrusted to an unstaked confidant was burned, the vault would have nothing to return it from
+    await program.methods.entrust(confidant.publicKey, entrustAmount, new BN(0)).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      confidantOffer: confidantOffer,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    try {
+      await program.methods.initVault().accounts({
+        staking: stakingPda,
+        fctrVault: fctrVault,
+        fctrMint: fctrMint,
+        owner: owner.publicKey,
+      }).rpc();
+      expect.fail("Switched with entrusted FCTR in custody");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('CantSwitchPoolMode');
+    }
+    expect((await program.account.staking.fetch(stakingPda)).escrowMode).to.be.false;
+
+    await program.methods.demandBack(confidant.publicKey).accounts({
+      staking: stakingPda,
+      principal: owner.publicKey,
+      principalInfo: principalInfo,
+      confidantInfo: confidantInfo,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principalFctrAccount.address,
+      bcdevMint: bcdevMint,
+      principalBcdevAccount: principalBcdevAccount.address,
+      fctrVault: fctrVault
+    }).rpc();
+
+    await program.methods.initVault().accounts({
+      staking: stakingPda,
+      fctrVault: fctrVault,
+      fctrMint: fctrMint,
+      owner: owner.publicKey,
+    }).rpc();
+
+    const staking = await program.account.staking.fetch(stakingPda);
+    expect(staking.escrowMode).to.be.true;
+    expect(staking.fctrVault.equals(fctrVault)).to.be.true;
+  })
+
+  it("Test liquid staking", async () => {
+    const liquidStaker = anchor.web3.Keypair.generate();
+    await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(liquidStaker.publicKey, 100 * anchor.web3.LAMPORTS_PER_SOL));
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), liquidStaker.publicKey.toBuffer()], program.programId);
+
+    const sfctrMint = await createMint(program.provider.connection, payer, stakingPda, null, 12);
+    await program.methods.setSfctrMint().accounts({
+      staking: stakingPda,
+      sfctrMint: sfctrMint,
+      owner: owner.publicKey,
+    }).rpc();
+
+    await program.methods.register().accounts({
+      staker: liquidStaker.publicKey,
+      stakerInfo: stakerInfo,
+      staking: stakingPda,
+      proofSigner: proofSigner.publicKey
+    }).signers([liquidStaker, proofSigner]).rpc()
+
+    const testAmount = new BN(10).mul(ONE_FCTR);
+    let fctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, liquidStaker.publicKey);
+    let bcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, liquidStaker.publicKey);
+    let sfctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, sfctrMint, liquidStaker.publicKey);
+
+    await program.methods.buyFctr(testAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: liquidStaker.publicKey,
+      stakerInfo: stakerInfo,
+      userFctrAccount: fctrAccount.address
+    }).signers([liquidStaker]).rpc();
+
+    await program.methods.stakeLiquid(testAmount).accounts({
+      staking: stakingPda,
+      stakerInfo: stakerInfo,
+      stakerFctrAccount: fctrAccount.address,
+      stakerSfctrAccount: sfctrAccount.address,
+      fctrMint: fctrMint,
+      sfctrMint: sfctrMint,
+      fctrVault: fctrVault,
+      staker: liquidStaker.publicKey
+    }).signers([liquidStaker]).rpc();
+
+    sfctrAccount = await getAccount(program.provider.connection, sfctrAccount.address);
+    expect(sfctrAccount.amount == BigInt(testAmount.toString(10))).to.be.true;
+
+    await new Promise(r => setTimeout(r, 2000));
+
+    const halfAmount = testAmount.divn(2);
+    await program.methods.redeemSfctr(halfAmount).accounts({
+      staking: stakingPda,
+      holderSfctrAccount: sfctrAccount.address,
+      holderFctrAccount: fctrAccount.address,
+      holderBcdevAccount: bcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      sfctrMint: sfctrMint,
+      fctrVault: fctrVault,
+      holder: liquidStaker.publicKey
+    }).remainingAccounts([
+      { pubkey: stakerInfo, isSigner: false, isWritable: true }
+    ]).signers([liquidStaker]).rpc();
+
+    fctrAccount = await getAccount(program.provider.connection, fctrAccount.address);
+    bcdevAccount = await getAccount(program.provider.connection, bcdevAccount.address);
+    expect(fctrAccount.amount == BigInt(halfAmount.toString(10))).to.be.true;
+    expect(bcdevAccount.amount > BigInt(0)).to.be.true;
+    const stakerInfoAccount = await program.account.stakerInfo.fetch(stakerInfo);
+    expect(stakerInfoAccount.ftcrAmount.eq(halfAmount)).to.be.true;
+
+    // sFCTR handed to a wallet that never registered is still redeemable
+    const holder = anchor.web3.Keypair.generate();
+    const holderSfctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, sfctrMint, holder.publicKey);
+    const holderFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, holder.publicKey);
+    const holderBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, holder.publicKey);
+    await transfer(program.provider.connection, payer, sfctrAccount.address, holderSfctrAccount.address, liquidStaker, BigInt(halfAmount.toString(10)));
+
+    await program.methods.redeemSfctr(halfAmount).accounts({
+      staking: stakingPda,
+      holderSfctrAccount: holderSfctrAccount.address,
+      holderFctrAccount: holderFctrAccount.address,
+      holderBcdevAccount: holderBcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      sfctrMint: sfctrMint,
+      fctrVault: fctrVault,
+      holder: holder.publicKey
+    }).signers([holder]).rpc();
+
+    const holderFctr = await getAccount(program.provider.connection, holderFctrAccount.address);
+    expect(holderFctr.amount == BigInt(halfAmount.toString(10))).to.be.true;
+  })
+
+  it("Test staking on behalf of another wallet", async () => {
+    const funder = anchor.web3.Keypair.generate();
+    const beneficiary = anchor.web3.Keypair.generate();
+    await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(funder.publicKey, 100 * anchor.web3.LAMPORTS_PER_SOL));
+    await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(beneficiary.publicKey, anchor.web3.LAMPORTS_PER_SOL));
+    const [funderInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), funder.publicKey.toBuffer()], program.programId);
+    const [beneficiaryInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), beneficiary.publicKey.toBuffer()], program.programId);
+
+    for (const [user, stakerInfo] of [[funder, funderInfo], [beneficiary, beneficiaryInfo]] as const) {
+      await program.methods.register().accounts({
+        staker: user.publicKey,
+        stakerInfo: stakerInfo,
+        staking: stakingPda,
+        proofSigner: proofSigner.publicKey
+      }).signers([user, proofSigner]).rpc()
+    }
+
+    const testAmount = new BN(10).mul(ONE_FCTR);
+    const funderFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, funder.publicKey);
+
+    await program.methods.buyFctr(testAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: funder.publicKey,
+      stakerInfo: funderInfo,
+      userFctrAccount: funderFctrAccount.address
+    }).signers([funder]).rpc();
+
+    // Someone else's position can't be opened on a longer lock
+    try {
+      await program.methods.stakeFor(beneficiary.publicKey, testAmount, 1).accounts({
+        staking: stakingPda,
+        payerInfo: funderInfo,
+        beneficiaryInfo: beneficiaryInfo,
+        payerFctrAccount: funderFctrAccount.address,
+        fctrMint: fctrMint,
+        fctrVault: fctrVault,
+        payer: funder.publicKey
+      }).signers([funder]).rpc();
+      expect.fail("Opened a position above the shortest tier for someone else");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('CantLockForeignStake');
+    }
+
+    await program.methods.stakeFor(beneficiary.publicKey, testAmount, 0).accounts({
+      staking: stakingPda,
+      payerInfo: funderInfo,
+      beneficiaryInfo: beneficiaryInfo,
+      payerFctrAccount: funderFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      payer: funder.publicKey
+    }).signers([funder]).rpc();
+
+    const position = await program.account.stakerInfo.fetch(beneficiaryInfo);
+    expect(position.stakeSize.eq(testAmount)).to.be.true;
+
+    // A second top-up later on keeps the lock where the first one put it
+    await new Promise(r => setTimeout(r, 2000));
+    await program.methods.buyFctr(testAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: funder.publicKey,
+      stakerInfo: funderInfo,
+      userFctrAccount: funderFctrAccount.address
+    }).signers([funder]).rpc();
+
+    await program.methods.stakeFor(beneficiary.publicKey, testAmount, 0).accounts({
+      staking: stakingPda,
+      payerInfo: funderInfo,
+      beneficiaryInfo: beneficiaryInfo,
+      payerFctrAccount: funderFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault,
+      payer: funder.publicKey
+    }).signers([funder]).rpc();
+
+    const toppedUp = await program.account.stakerInfo.fetch(beneficiaryInfo);
+    expect(toppedUp.stakeSize.eq(testAmount.muln(2))).to.be.true;
+    expect(toppedUp.lockEnd.eq(position.lockEnd)).to.be.true;
+    expect(toppedUp.stakeTime.eq(position.stakeTime)).to.be.true;
+  })
+
+  it("Test balance sync", async () => {
+    const [confidantInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), confidant.publicKey.toBuffer()], program.programId);
+    const ownerFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    const confidantFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, confidant.publicKey);
+    const confidantBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, confidant.publicKey);
+
+    // The token accounts have to be the staker's own
+    try {
+      await program.methods.syncBalances(confidant.publicKey).accounts({
+        staking: stakingPda,
+        stakerInfo: confidantInfo,
+        stakerFctrAccount: ownerFctrAccount.address,
+        stakerBcdevAccount: confidantBcdevAccount.address
+      }).rpc();
+      expect.fail("Synced against someone else's token account");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('ConstraintTokenOwner');
+    }
+
+    // Anyone may sync someone else's balances, here the owner syncs the confidant's
+    await program.methods.syncBalances(confidant.publicKey).accounts({
+      staking: stakingPda,
+      stakerInfo: confidantInfo,
+      stakerFctrAccount: confidantFctrAccount.address,
+      stakerBcdevAccount: confidantBcdevAccount.address
+    }).rpc();
+
+    const info = await program.account.stakerInfo.fetch(confidantInfo);
+    const custodied = info.isStaked ? BigInt(0) : BigInt(info.entrustedFctr.toString(10));
+    expect(BigInt(info.ftcrAmount.toString(10)) <= confidantFctrAccount.amount + custodied).to.be.true;
+    expect(BigInt(info.bcdevAmount.toString(10)) <= confidantBcdevAccount.amount).to.be.true;
+    expect(info.driftDetected).to.be.false;
+  })
+
+  it("Test history", async () => {
+    const testAmount = new BN(10).mul(ONE_FCTR);
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+    const [history,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("history"), owner.publicKey.toBuffer()], program.programId);
+    const userFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    const paidBefore = (await program.account.stakerInfo.fetch(stakerInfo)).lifetimeSolPaid;
+
+    await program.methods.initHistory().accounts({
+      staker: owner.publicKey,
+      stakerInfo: stakerInfo,
+      history: history
+    }).rpc();
+
+    await program.methods.buyFctr(testAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: owner.publicKey,
+      stakerInfo: stakerInfo,
+      userFctrAccount: userFctrAccount.address
+    }).remainingAccounts([{ pubkey: history, isWritable: true, isSigner: false }]).rpc();
+
+    const entries = (await program.account.stakerHistory.fetch(history)).entries;
+    expect(entries[0].kind).to.deep.equal({ buyFctr: {} });
+    expect(entries[0].amount.eq(testAmount)).to.be.true;
+    expect((await program.account.stakerInfo.fetch(stakerInfo)).lifetimeSolPaid.gt(paidBefore)).to.be.true;
+  })
+
+  it("Test quotes", async () => {
+    const testAmount = new BN(10).mul(ONE_FCTR);
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), owner.publicKey.toBuffer()], program.programId);
+
+    const buyQuote = await program.methods.quoteBuyFctr(testAmount).view();
+    expect(buyQuote.eq(testAmount.mul(new BN(anchor.web3.LAMPORTS_PER_SOL)).div(ONE_FCTR).div(new BN(109)))).to.be.true;
+
+    const sellQuote = await program.methods.quoteSellFctr(testAmount).view();
+    expect(sellQuote.eq(testAmount.mul(new BN(anchor.web3.LAMPORTS_PER_SOL)).div(ONE_FCTR).div(new BN(101)))).to.be.true;
+
+    const bcdevQuote = await program.methods.quoteSellBcdev(ONE_BCDEV).view();
+    expect(bcdevQuote.eq(new BN(anchor.web3.LAMPORTS_PER_SOL).div(new BN(11)))).to.be.true;
+
+    const userFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    await program.methods.buyFctr(testAmount).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: owner.publicKey,
+      stakerInfo: stakerInfo,
+      userFctrAccount: userFctrAccount.address
+    }).rpc();
+    await program.methods.stake(0).accounts({
+      staking: stakingPda,
+      stakerInfo: stakerInfo,
+      stakerFctrAccount: userFctrAccount.address,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault
+    }).rpc();
+
+    await new Promise(r => setTimeout(r, 2000));
+
+    const pending = await program.methods.pendingRewards(owner.publicKey).accounts({ staking: stakingPda, stakerInfo: stakerInfo }).view();
+    const info = await program.account.stakerInfo.fetch(stakerInfo);
+    // The shortest tier earns at the base rate, so the view adds stake * rpr for every second since the last accrual
+    expect(info.lockTier).to.equal(0);
+    const perSecond = info.stakeSize.mul(info.userRpr);
+    const accrued = pending.sub(info.pendingBcdevReward);
+    expect(accrued.mod(perSecond).isZero()).to.be.true;
+    expect(accrued.div(perSecond).gten(2)).to.be.true;
+  })
+
+  it("Test early exit penalties", async () => {
+    const stakeAmount = new BN(10).mul(ONE_FCTR);
+    const penaltyBps = new BN(5000);
+
+    // Stays staked throughout and gets its share of what goes to the reward pool
+    const stayer = await newStaker(stakeAmount);
+    await stakeAs(stayer, 0);
+    const stayerDebt = (await program.account.stakerInfo.fetch(stayer.stakerInfo)).fctrBonusDebt;
+
+    const penaltyRoutes = [
+      [{ stake: {} }, { rewardPool: {} }],
+      [{ reward: {} }, { rewardPool: {} }],
+      [{ stake: {} }, { treasury: {} }],
+      [{ reward: {} }, { treasury: {} }]
+    ];
+    for (const [source, destination] of penaltyRoutes) {
+      await program.methods.setEarlyExit(true, penaltyBps, source as any, destination as any).accounts({
+        staking: stakingPda,
+        owner: owner.publicKey,
+      }).rpc();
+
+      // Leaves long before the longest tier's lock is over, having earned some reward
+      const exiter = await newStaker(stakeAmount);
+      await stakeAs(exiter, 3);
+      await new Promise(r => setTimeout(r, 2000));
+
+      const before = await program.account.staking.fetch(stakingPda);
+      await unstakeAs(exiter);
+      const after = await program.account.staking.fetch(stakingPda);
+
+      const fctrReceived = await tokenBalance(exiter.fctrAccount);
+      const bcdevReceived = await tokenBalance(exiter.bcdevAccount);
+      const remainingStake = before.totalStaked.sub(stakeAmount);
+      expect(bcdevReceived.gtn(0)).to.be.true;
+
+      if ("stake" in source) {
+        const forfeited = stakeAmount.mul(penaltyBps).divn(10000);
+        expect(fctrReceived.eq(stakeAmount.sub(forfeited))).to.be.true;
+        if ("rewardPool" in destination) {
+          // Compounded into the remaining stakes
+          expect(after.fctrBonusPerShare.sub(before.fctrBonusPerShare).eq(forfeited.mul(BONUS_PRECISION).div(remainingStake))).to.be.true;
+          expect(after.totalStaked.eq(remainingStake.add(forfeited))).to.be.true;
+        } else {
+          expect(after.treasuryFctr.sub(before.treasuryFctr).eq(forfeited)).to.be.true;
+          expect(after.totalStaked.eq(remainingStake)).to.be.true;
+        }
+      } else {
+        expect(fctrReceived.eq(stakeAmount)).to.be.true;
+        expect(after.totalStaked.eq(remainingStake)).to.be.true;
+        // Half the reward rounded down is forfeited, so the staker keeps the odd unit
+        const forfeited = [bcdevReceived, bcdevReceived.subn(1)];
+        if ("rewardPool" in destination) {
+          const bonusPerShare = after.bcdevBonusPerShare.sub(before.bcdevBonusPerShare);
+          expect(forfeited.some(amount => bonusPerShare.eq(amount.mul(BONUS_PRECISION).div(remainingStake)))).to.be.true;
+        } else {
+          const treasuryBcdev = after.treasuryBcdev.sub(before.treasuryBcdev);
+          expect(forfeited.some(amount => treasuryBcdev.eq(amount))).to.be.true;
+        }
+      }
+    }
+
+    // Past its lock the stayer leaves without a penalty, its stake grown by the compounded FCTR
+    const staking = await program.account.staking.fetch(stakingPda);
+    await unstakeAs(stayer);
+    const stayerFctr = await tokenBalance(stayer.fctrAccount);
+    expect(stayerFctr.eq(stakeAmount.add(stakeAmount.mul(staking.fctrBonusPerShare).div(BONUS_PRECISION)).sub(stayerDebt))).to.be.true;
+    expect(stayerFctr.gt(stakeAmount)).to.be.true;
+
+    // The treasury holds what the other two exits forfeited
+    const ownerFctrAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, fctrMint, owner.publicKey);
+    const ownerBcdevAccount = await getOrCreateAssociatedTokenAccount(program.provider.connection, payer, bcdevMint, owner.publicKey);
+    const ownerFctrBefore = await tokenBalance(ownerFctrAccount.address);
+    const ownerBcdevBefore = await tokenBalance(ownerBcdevAccount.address);
+    expect(staking.treasuryFctr.gtn(0)).to.be.true;
+    expect(staking.treasuryBcdev.gtn(0)).to.be.true;
+
+    await program.methods.withdrawTreasury().accounts({
+      staking: stakingPda,
+      owner: owner.publicKey,
+      ownerFctrAccount: ownerFctrAccount.address,
+      ownerBcdevAccount: ownerBcdevAccount.address,
+      bcdevMint: bcdevMint,
+      fctrMint: fctrMint,
+      fctrVault: fctrVault
+    }).rpc();
+
+    expect((await tokenBalance(ownerFctrAccount.address)).sub(ownerFctrBefore).eq(staking.treasuryFctr)).to.be.true;
+    expect((await tokenBalance(ownerBcdevAccount.address)).sub(ownerBcdevBefore).eq(staking.treasuryBcdev)).to.be.true;
+    const withdrawn = await program.account.staking.fetch(stakingPda);
+    expect(withdrawn.treasuryFctr.isZero()).to.be.true;
+    expect(withdrawn.treasuryBcdev.isZero()).to.be.true;
+
+    await program.methods.setEarlyExit(false, new BN(1000), { stake: {} }, { rewardPool: {} }).accounts({
+      staking: stakingPda,
+      owner: owner.publicKey,
+    }).rpc();
+  })
+
+  it("Test slashing a confidant", async () => {
+    const ownFctr = new BN(20).mul(ONE_FCTR);
+    const entrustAmount = new BN(10).mul(ONE_FCTR);
+    const principal = await newStaker(ownFctr);
+    const confidant = await newStaker(ownFctr);
+    await publishOfferAs(confidant, 0);
+    const entrustRecord = await entrustAs(principal, confidant, entrustAmount);
+    await stakeAs(confidant, 0);
+
+    const slash = (authority: anchor.web3.PublicKey) => program.methods.slashConfidant(confidant.user.publicKey, principal.user.publicKey, new BN(5000)).accounts({
+      staking: stakingPda,
+      authority: authority,
+      confidantInfo: confidant.stakerInfo,
+      principalInfo: principal.stakerInfo,
+      entrustRecord: entrustRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: principal.fctrAccount,
+      fctrVault: fctrVault
+    });
+
+    // Only the owner or the arbiter may slash
+    try {
+      await slash(principal.user.publicKey).signers([principal.user]).rpc();
+      expect.fail("Slashed by the principal");
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal('NotTheArbiter');
+    }
+
+    const stakingBefore = await program.account.staking.fetch(stakingPda);
+    const confidantBefore = await program.account.stakerInfo.fetch(confidant.stakerInfo);
+    const principalBefore = await program.account.stakerInfo.fetch(principal.stakerInfo);
+    const principalFctrBefore = await tokenBalance(principal.fctrAccount);
+    expect(confidantBefore.stakeSize.eq(ownFctr.add(entrustAmount))).to.be.true;
+
+    await slash(owner.publicKey).rpc();
+
+    // Half of the confidant's own stake goes to the principal, the entrusted FCTR is left alone
+    const slashed = ownFctr.divn(2);
+    const confidantAfter = await program.account.stakerInfo.fetch(confidant.stakerInfo);
+    expect(confidantAfter.stakeSize.eq(confidantBefore.stakeSize.sub(slashed))).to.be.true;
+    expect(confidantAfter.entrustedFctr.eq(entrustAmount)).to.be.true;
+    expect(confidantAfter.confidantDisputes.toNumber()).to.equal(confidantBefore.confidantDisputes.toNumber() + 1);
+    expect((await program.account.staking.fetch(stakingPda)).totalStaked.eq(stakingBefore.totalStaked.sub(slashed))).to.be.true;
+    expect((await program.account.stakerInfo.fetch(principal.stakerInfo)).ftcrAmount.eq(principalBefore.ftcrAmount.add(slashed))).to.be.true;
+    expect((await tokenBalance(principal.fctrAccount)).eq(principalFctrBefore.add(slashed))).to.be.true;
+  })
+
+  it("Test splitting a principal's reward with the confidant", async () => {
+    const ownFctr = new BN(20).mul(ONE_FCTR);
+    const entrustAmount = new BN(10).mul(ONE_FCTR);
+    const commissionBps = 2000;
+    const confidant = await newStaker(ownFctr);
+    await publishOfferAs(confidant, commissionBps);
+
+    // The commission is the offer's share of the principal's reward, rounded down
+    const expectSplit = async (split, principal: TestStaker) => {
+      expect(split.confidant.equals(confidant.user.publicKey)).to.be.true;
+      expect(split.principal.equals(principal.user.publicKey)).to.be.true;
+      expect(split.confidantCommission.gtn(0)).to.be.true;
+      const reward = split.principalReward.add(split.confidantCommission);
+      expect(split.confidantCommission.eq(reward.muln(commissionBps).divn(10000))).to.be.true;
+      expect((await tokenBalance(principal.bcdevAccount)).eq(split.principalReward)).to.be.true;
+    };
+
+    // Demanded back out of the running stake, the commission stays in the confidant's pending reward
+    const firstPrincipal = await newStaker(ownFctr);
+    const firstRecord = await entrustAs(firstPrincipal, confidant, entrustAmount);
+    await stakeAs(confidant, 0);
+    await new Promise(r => setTimeout(r, 2000));
+
+    const demandBack = await program.methods.demandBack(confidant.user.publicKey).accounts({
+      staking: stakingPda,
+      principal: firstPrincipal.user.publicKey,
+      principalInfo: firstPrincipal.stakerInfo,
+      confidantInfo: confidant.stakerInfo,
+      entrustRecord: firstRecord,
+      fctrMint: fctrMint,
+      principalFctrAccount: firstPrincipal.fctrAccount,
+      bcdevMint: bcdevMint,
+      principalBcdevAccount: firstPrincipal.bcdevAccount,
+      fctrVault: fctrVault
+    }).signers([firstPrincipal.user]).rpc({ commitment: "confirmed" });
+
+    const demandBackSplits = await rewardSplits(demandBack);
+    expect(demandBackSplits.length).to.equal(1);
+    await expectSplit(demandBackSplits[0], firstPrincipal);
+    expect((await program.account.stakerInfo.fetch(confidant.stakerInfo)).pendingBcdevReward.gte(demandBackSplits[0].confidantCommission)).to.be.true;
+
+    // Released by the confidant unstaking, the commission is paid out to the confidant along with their own reward
+    await program.methods.buyFctr(ownFctr).accounts({
+      staking: stakingPda,
+      fctrMint: fctrMint,
+      user: confidant.user.publicKey,
+      stakerInfo: confidant.stakerInfo,
+      userFctrAccount: confidant.fctrAccount
+    }).signers([confidant.user]).rpc();
+    const secondPrincipal = await newStaker(ownFctr);
+    const secondRecord = await entrustAs(secondPrincipal, confidant, entrustAmount);
+    await new Promise(r => setTimeout(r, 2000));
+
+    const unstake = await unstakeAs(confidant, [
+      { pubkey: secondPrincipal.fctrAccount, isWritable: true, isSigner: false },
+      { pubkey: secondPrincipal.bcdevAccount, isWritable: true, isSigner: false },
+      { pubkey: secondPrincipal.stakerInfo, isWritable: true, isSigner: false },
+      { pubkey: secondRecord, isWritable: true, isSigner: false },
+      { pubkey: secondPrincipal.user.publicKey, isWritable: true, isSigner: false }
+    ]);
+
+    const unstakeSplits = await rewardSplits(unstake);
+    expect(unstakeSplits.length).to.equal(1);
+    await expectSplit(unstakeSplits[0], secondPrincipal);
+    expect((await tokenBalance(secondPrincipal.fctrAccount)).eq(ownFctr)).to.be.true;
+    expect((await tokenBalance(confidant.bcdevAccount)).gt(unstakeSplits[0].confidantCommission)).to.be.true;
+  })
+
+  it("Test closing staker info", async () => {
+    const user = anchor.web3.Keypair.generate();
+    await program.provider.connection.confirmTransaction(await program.provider.connection.requestAirdrop(user.publicKey, anchor.web3.LAMPORTS_PER_SOL));
+    const [stakerInfo,] = await anchor.web3.PublicKey.findProgramAddress([utf8.encode("staker-info"), user.publicKey.toBuffer()], program.programId);
+
+    await program.methods.register().accounts({
+      staker: user.publicKey,
+      stakerInfo: stakerInfo,
+      staking: stakingPda,
+      proofSigner: proofSigner.publicKey
+    }).signers([user, proofSigner]).rpc()
+
+    await program.methods.closeStakerInfo().accounts({
+      staking: stakingPda,
+      staker: user.publicKey,
+      stakerInfo: stakerInfo
+    }).signers([user]).rpc();
+
+    expect(await program.account.stakerInfo.fetchNullable(stakerInfo)).to.be.null;
   })
 
 });